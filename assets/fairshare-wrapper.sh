@@ -4,6 +4,35 @@
 
 set -e
 
+# Print how to run the subcommand in "$@": "direct" or "pkexec". The subcommand is
+# the first argument after any global options (e.g. `fairshare -q admin ...`).
+fairshare_route() {
+    local subcommand=""
+    while [[ $# -gt 0 ]]; do
+        case "$1" in
+            -q|--quiet|--debug) shift ;;
+            # --config takes a value, given separately or as --config=PATH
+            --config) shift 2 || break ;;
+            --config=*) shift ;;
+            *) subcommand="$1"; break ;;
+        esac
+    done
+
+    case "$subcommand" in
+        # Admin commands require sudo; the user must have already invoked with it
+        admin) echo direct ;;
+        # Commands that only print generated text, and the read-only top view, don't need privileges
+        completions|man|version|top) echo direct ;;
+        # Regular user command - pkexec handles authentication and sets PKEXEC_UID
+        *) echo pkexec ;;
+    esac
+}
+
+# Sourced (e.g. by the tests) only to define fairshare_route
+if [[ "${BASH_SOURCE[0]}" != "$0" ]]; then
+    return 0
+fi
+
 # Auto-detect binary location (supports both package and local installation)
 if [[ -x "/usr/libexec/fairshare-bin" ]]; then
     FAIRSHARE_BIN="/usr/libexec/fairshare-bin"
@@ -20,14 +49,8 @@ else
     exit 1
 fi
 
-# Detect if running admin command
-# Admin commands start with "admin" subcommand and require sudo
-if [[ "${1:-}" == "admin" ]]; then
-    # Admin command - execute directly without pkexec
-    # User must have already invoked with sudo
+if [[ "$(fairshare_route "$@")" == "direct" ]]; then
     exec "$FAIRSHARE_BIN" "$@"
 fi
 
-# Regular user command - use pkexec for privilege escalation
-# pkexec will handle authentication and set PKEXEC_UID
 exec pkexec "$FAIRSHARE_BIN" "$@"
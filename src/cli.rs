@@ -1,5 +1,7 @@
 use clap::builder::RangedU64ValueParser;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use std::io;
+use std::time::Duration;
 
 /// Minimum number of CPUs that must be requested
 pub const MIN_CPU: u32 = 1;
@@ -16,6 +18,156 @@ pub const MIN_DISK: u32 = 1;
 /// Maximum amount of disk (in GB) that can be requested
 pub const MAX_DISK: u32 = 10000;
 
+/// Smallest IOWeight systemd accepts
+pub const MIN_IO_WEIGHT: u32 = 1;
+/// Largest IOWeight systemd accepts
+pub const MAX_IO_WEIGHT: u32 = 10000;
+
+/// Shortest CPU quota period systemd accepts for CPUQuotaPeriodSec
+pub const MIN_CPU_PERIOD: Duration = Duration::from_millis(1);
+/// Longest CPU quota period systemd accepts for CPUQuotaPeriodSec
+pub const MAX_CPU_PERIOD: Duration = Duration::from_secs(1);
+
+/// Longest grace period `release --grace` will wait for processes to exit
+pub const MAX_GRACE: Duration = Duration::from_secs(3600);
+
+/// A hypothetical `USER:CPU:MEM` request given to `fairshare simulate --add`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulatedRequest {
+    pub user: String,
+    pub cpu: u32,
+    pub mem: u32,
+}
+
+/// Parse `USER:CPU:MEM` (e.g. "alice:8:16") for `simulate --add`
+pub fn parse_simulated_request(value: &str) -> Result<SimulatedRequest, String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() != 3 || parts[0].is_empty() {
+        return Err(format!(
+            "'{}' must be USER:CPU:MEM (e.g. alice:8:16)",
+            value
+        ));
+    }
+
+    let cpu = parts[1]
+        .parse::<u32>()
+        .ok()
+        .filter(|c| (MIN_CPU..=MAX_CPU).contains(c))
+        .ok_or_else(|| format!("CPU '{}' must be {}-{}", parts[1], MIN_CPU, MAX_CPU))?;
+    let mem = parts[2]
+        .parse::<u32>()
+        .ok()
+        .filter(|m| (MIN_MEM..=MAX_MEM).contains(m))
+        .ok_or_else(|| format!("memory '{}' must be {}-{} GB", parts[2], MIN_MEM, MAX_MEM))?;
+
+    Ok(SimulatedRequest {
+        user: parts[0].to_string(),
+        cpu,
+        mem,
+    })
+}
+
+/// `request --mem`: GB (possibly fractional), or a percentage of the machine's memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemSize {
+    Gb(f64),
+    Percent(u32),
+}
+
+impl MemSize {
+    /// GB this stands for on a machine with `total_mem_gb`. Percentages round
+    /// down but never below 1, like `--mem-pct`.
+    pub fn resolve(self, total_mem_gb: f64) -> f64 {
+        match self {
+            MemSize::Gb(gb) => gb,
+            MemSize::Percent(pct) => (total_mem_gb * pct as f64 / 100.0).floor().max(1.0),
+        }
+    }
+}
+
+/// Parse `request --mem`: GB ("16" or "1.5", 1-10000) or a percentage of total memory ("50%", 1-100)
+pub fn parse_mem_size(value: &str) -> Result<MemSize, String> {
+    let value = value.trim();
+    if let Some(pct) = value.strip_suffix('%') {
+        return pct
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|p| (1..=100).contains(p))
+            .map(MemSize::Percent)
+            .ok_or_else(|| format!("'{}' must be a percentage from 1% to 100%", value));
+    }
+
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|m| (MIN_MEM as f64..=MAX_MEM as f64).contains(m))
+        .map(MemSize::Gb)
+        .ok_or_else(|| {
+            format!(
+                "'{}' must be {}-{} GB or a percentage (e.g. 50%)",
+                value, MIN_MEM, MAX_MEM
+            )
+        })
+}
+
+/// Parse a CPU quota period such as "10ms", "500us" or "1s" and check it is
+/// within systemd's accepted range (1ms-1s)
+pub fn parse_cpu_period(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("'{}' needs a unit: us, ms or s (e.g. 10ms)", value))?;
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid duration (e.g. 10ms)", value))?;
+    let period = match unit {
+        "us" => Duration::from_micros(number),
+        "ms" => Duration::from_millis(number),
+        "s" => Duration::from_secs(number),
+        _ => return Err(format!("unknown unit '{}': use us, ms or s", unit)),
+    };
+
+    if !(MIN_CPU_PERIOD..=MAX_CPU_PERIOD).contains(&period) {
+        return Err(format!("'{}' is outside the accepted range 1ms-1s", value));
+    }
+
+    Ok(period)
+}
+
+/// Parse a grace period such as "30s" or "2m" (a bare number is seconds), up to
+/// `MAX_GRACE`
+pub fn parse_grace(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid duration (e.g. 30s)", value))?;
+    let grace = match unit {
+        "" | "s" => Duration::from_secs(number),
+        "m" => Duration::from_secs(number.saturating_mul(60)),
+        _ => return Err(format!("unknown unit '{}': use s or m", unit)),
+    };
+
+    if grace > MAX_GRACE {
+        return Err(format!("'{}' is longer than the maximum of 1h", value));
+    }
+
+    Ok(grace)
+}
+
+/// Whether `request --all` prints its ASCII art: only when asked for with `--fun`,
+/// and never in `--quiet` mode or when stdout is piped into a log or script
+pub fn show_request_all_art(all: bool, fun: bool, quiet: bool, stdout_is_tty: bool) -> bool {
+    all && fun && !quiet && stdout_is_tty
+}
+
 #[derive(Parser)]
 #[command(
     name = "fairshare",
@@ -23,24 +175,86 @@ pub const MAX_DISK: u32 = 10000;
     about = "Systemd-based resource manager for multi-user Linux systems"
 )]
 pub struct Cli {
+    /// Suppress confirmation messages and decorations (errors are still printed)
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Policy file to use instead of /etc/fairshare/policy.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<std::path::PathBuf>,
+
+    /// Log every systemctl invocation and its exit status to stderr
+    #[arg(long, global = true)]
+    pub debug: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// Render the man page for the whole command tree. The top-level page comes first,
+/// followed by one page per subcommand (fairshare-admin, fairshare-admin-setup, ...).
+pub fn render_man_page(w: &mut dyn io::Write) -> io::Result<()> {
+    let mut cmd = Cli::command().disable_help_subcommand(true);
+    cmd.build();
+    render_man_tree(&cmd, w)
+}
+
+fn render_man_tree(cmd: &clap::Command, w: &mut dyn io::Write) -> io::Result<()> {
+    clap_mangen::Man::new(cmd.clone()).render(w)?;
+
+    for sub in cmd.get_subcommands().filter(|s| !s.is_hide_set()) {
+        render_man_tree(sub, w)?;
+    }
+
+    Ok(())
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Show system totals and all user allocations
-    Status,
+    Status {
+        /// Order of the per-user table: cpu/mem largest first, uid/user ascending
+        #[arg(long, value_enum, default_value_t = StatusSort::Uid)]
+        sort: StatusSort,
+
+        /// Only show the system overview, skipping the per-user table (one systemctl query)
+        #[arg(long)]
+        totals_only: bool,
+
+        /// Table borders: full (Unicode box drawing), ascii, minimal or none
+        #[arg(long, value_enum, default_value_t = TableStyle::Full)]
+        table_style: TableStyle,
+    },
 
     /// Request resources (e.g. --cpu 4 --mem 8 --disk 20, or --all for all available)
     Request {
         /// Number of CPUs to request (1-1000)
-        #[arg(long, required_unless_present = "all", value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
+        #[arg(long, required_unless_present_any = ["all", "cpu_pct", "cpu_max", "interactive"], value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
         cpu: Option<u32>,
 
-        /// Amount of memory in GB to request (1-10000)
-        #[arg(long, required_unless_present = "all", value_parser = RangedU64ValueParser::<u32>::new().range(MIN_MEM as u64..=MAX_MEM as u64))]
-        mem: Option<u32>,
+        /// Amount of memory in GB to request (1-10000, decimals allowed, e.g. 1.5), or a percentage of total memory (e.g. 50%)
+        #[arg(long, required_unless_present_any = ["all", "mem_pct", "interactive"], value_parser = parse_mem_size)]
+        mem: Option<MemSize>,
+
+        /// Memory in GB protected from reclaim under memory pressure (MemoryMin); must not exceed --mem
+        #[arg(long, value_name = "GB", value_parser = RangedU64ValueParser::<u32>::new().range(MIN_MEM as u64..=MAX_MEM as u64))]
+        mem_min: Option<u32>,
+
+        /// Request this percentage of the machine's CPUs instead of --cpu (1-100, rounded down)
+        #[arg(long, value_name = "PERCENT", conflicts_with_all = ["cpu", "all"], value_parser = RangedU64ValueParser::<u32>::new().range(1..=100))]
+        cpu_pct: Option<u32>,
+
+        /// Request this percentage of the machine's memory instead of --mem (1-100, rounded down)
+        #[arg(long, value_name = "PERCENT", conflicts_with_all = ["mem", "all"], value_parser = RangedU64ValueParser::<u32>::new().range(1..=100))]
+        mem_pct: Option<u32>,
+
+        /// Burst ceiling in CPUs (CPUQuota), used instead of --cpu with --cpu-guaranteed
+        #[arg(long, value_name = "CPUS", conflicts_with_all = ["cpu", "cpu_pct", "all", "interactive"], value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
+        cpu_max: Option<u32>,
+
+        /// CPUs guaranteed under contention (sets CPUWeight); must not exceed --cpu-max
+        #[arg(long, value_name = "CPUS", requires = "cpu_max", value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
+        cpu_guaranteed: Option<u32>,
 
         /// Amount of disk in GB to request (1-10000)
         #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_DISK as u64..=MAX_DISK as u64))]
@@ -49,13 +263,118 @@ pub enum Commands {
         /// Request all remaining available resources
         #[arg(long, conflicts_with_all = ["cpu", "mem", "disk"])]
         all: bool,
+
+        /// Celebrate --all with ASCII art (only when stdout is a terminal)
+        #[arg(long, requires = "all")]
+        fun: bool,
+
+        /// Prompt for CPU and memory, suggesting what is available, then confirm
+        #[arg(long, conflicts_with_all = ["cpu", "mem", "cpu_pct", "mem_pct", "all", "best_effort", "wait"])]
+        interactive: bool,
+
+        /// Make the allocation temporary (dropped at reboot instead of persisted)
+        #[arg(long)]
+        runtime: bool,
+
+        /// Wait for resources to free up instead of failing (optional timeout in seconds)
+        #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "0", conflicts_with = "all")]
+        wait: Option<u64>,
+
+        /// CPU quota enforcement period (1ms-1s, e.g. 10ms). Shorter periods throttle more smoothly.
+        #[arg(long, value_name = "DURATION", value_parser = parse_cpu_period)]
+        cpu_period: Option<Duration>,
+
+        /// Proportional share of disk IO when it is contended (1-10000, systemd default 100).
+        /// Falls back to the policy's io_weight; not set when neither is given.
+        #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_IO_WEIGHT as u64..=MAX_IO_WEIGHT as u64))]
+        io_weight: Option<u32>,
+
+        /// Show how the available resources were worked out, whether or not the request fits
+        #[arg(long)]
+        explain: bool,
+
+        /// If the full request doesn't fit, take whatever is available (down to --min-cpu/--min-mem)
+        #[arg(long, conflicts_with_all = ["all", "wait"])]
+        best_effort: bool,
+
+        /// Smallest CPU grant --best-effort will accept (default: 1)
+        #[arg(long, requires = "best_effort", default_value_t = MIN_CPU, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
+        min_cpu: u32,
+
+        /// Smallest memory grant in GB --best-effort will accept (default: 1)
+        #[arg(long, requires = "best_effort", default_value_t = MIN_MEM, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_MEM as u64..=MAX_MEM as u64))]
+        min_mem: u32,
+
+        /// Allow lowering memory below what your processes currently use
+        #[arg(long)]
+        force: bool,
+
+        /// Delegate your slice's cgroup subtree to you (Delegate=yes), e.g. for a nested scheduler; needs allow_delegation in the policy
+        #[arg(long)]
+        delegate: bool,
+
+        /// Run every check, then print the systemctl command that would be run instead of running it
+        #[arg(long, conflicts_with = "wait")]
+        preview_command: bool,
     },
 
     /// Release all signed-out resources back to default
-    Release,
+    Release {
+        /// First SIGTERM the processes in your slice and give them this long to
+        /// exit (e.g. 30s, 2m) before the limits are reverted
+        #[arg(long, value_name = "DURATION", value_parser = parse_grace)]
+        grace: Option<Duration>,
+    },
 
     /// Show current user's resource usage info
-    Info,
+    Info {
+        /// Show another user's allocation instead (username or UID, root only)
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Stable key=value output for scripts (unset limits print as "unset")
+        #[arg(long)]
+        porcelain: bool,
+    },
+
+    /// List every user slice's limits (--json for monitoring)
+    Allocations {
+        /// Print a JSON array of {uid, username, cpu_cores, mem_bytes} instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show availability after hypothetical requests, without applying anything
+    Simulate {
+        /// A hypothetical request as USER:CPU:MEM (e.g. alice:8:16); repeatable
+        #[arg(long = "add", value_name = "USER:CPU:MEM", required = true, value_parser = parse_simulated_request)]
+        add: Vec<SimulatedRequest>,
+    },
+
+    /// Live view of user slices and their CPU/memory use (q to quit)
+    Top {
+        /// Seconds between refreshes (1-3600)
+        #[arg(long, default_value_t = 2, value_parser = RangedU64ValueParser::<u64>::new().range(1..=3600))]
+        interval: u64,
+    },
+
+    /// Generate shell completion scripts (e.g. fairshare completions bash > /etc/bash_completion.d/fairshare)
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Show the fairshare version
+    Version {
+        /// Also show build and host details for bug reports
+        #[arg(long)]
+        verbose: bool,
+    },
+
+    /// Print the roff man page to stdout (e.g. fairshare man > fairshare.1)
+    #[command(hide = true)]
+    Man,
 
     /// Admin operations - setup/uninstall global resource limits (requires root)
     Admin {
@@ -68,25 +387,30 @@ pub enum Commands {
 pub enum AdminSubcommands {
     /// Setup global baseline
     Setup {
-        /// Default number of CPUs per user (1-1000)
-        #[arg(long, default_value_t = 1, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
-        cpu: u32,
+        /// Preset defaults and reserves sized from this machine's totals.
+        /// --cpu, --mem, --cpu-reserve and --mem-reserve override the preset.
+        #[arg(long, value_enum)]
+        profile: Option<SetupProfile>,
 
-        /// Default amount of memory per user in GB (1-10000)
-        #[arg(long, default_value_t = 2, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_MEM as u64..=MAX_MEM as u64))]
-        mem: u32,
+        /// Default number of CPUs per user (1-1000, default: 1)
+        #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
+        cpu: Option<u32>,
+
+        /// Default amount of memory per user in GB (1-10000, default: 2)
+        #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_MEM as u64..=MAX_MEM as u64))]
+        mem: Option<u32>,
 
         /// Default amount of disk per user in GB (1-10000). Only applied when --disk-partition is also set.
         #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_DISK as u64..=MAX_DISK as u64))]
         disk: Option<u32>,
 
         /// System CPU reserve (1-1000, default: 2)
-        #[arg(long, default_value_t = 2, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
-        cpu_reserve: u32,
+        #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
+        cpu_reserve: Option<u32>,
 
         /// System memory reserve in GB (1-10000, default: 4)
-        #[arg(long, default_value_t = 4, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_MEM as u64..=MAX_MEM as u64))]
-        mem_reserve: u32,
+        #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_MEM as u64..=MAX_MEM as u64))]
+        mem_reserve: Option<u32>,
 
         /// System disk reserve in GB (1-10000, default: 4). Only used when --disk is set.
         #[arg(long, default_value_t = 4, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_DISK as u64..=MAX_DISK as u64))]
@@ -95,6 +419,18 @@ pub enum AdminSubcommands {
         /// System disk partition to monitor (e.g., /home, /data). Required for disk quotas.
         #[arg(long)]
         disk_partition: Option<String>,
+
+        /// Default TasksMax per user (processes and threads, at least 1). The distro default applies when unset.
+        #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+        tasks: Option<u32>,
+
+        /// Print the files setup would write instead of applying anything
+        #[arg(long)]
+        print: bool,
+
+        /// Also push the new default to running user slices that are at the old default
+        #[arg(long, conflicts_with = "print")]
+        apply_to_running: bool,
     },
 
     /// Uninstall global defaults and remove all fairshare admin configuration
@@ -134,6 +470,18 @@ pub enum AdminSubcommands {
         #[arg(long)]
         disk_partition: Option<String>,
 
+        /// Default TasksMax per user (processes and threads, at least 1). The distro default applies when unset.
+        #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+        tasks: Option<u32>,
+
+        /// Only rewrite the defaults and reserves; leave users' current allocations in place
+        #[arg(long)]
+        keep_allocations: bool,
+
+        /// Also push the new default to running user slices (those without an allocation when --keep-allocations is set)
+        #[arg(long)]
+        apply_to_running: bool,
+
         /// Skip confirmation prompt
         #[arg(long)]
         force: bool,
@@ -161,4 +509,204 @@ pub enum AdminSubcommands {
         #[arg(long)]
         force: bool,
     },
+
+    /// Set many users' allocations from a TOML file, all or nothing
+    Apply {
+        /// TOML file with a [users] table, e.g. alice = { cpu = 4, mem = 8 }
+        #[arg(long, value_name = "PATH", required_unless_present = "json_stdin")]
+        from_file: Option<std::path::PathBuf>,
+
+        /// Read a JSON array of {"uid", "cpu", "mem"} from stdin and print a JSON
+        /// array of per-entry results
+        #[arg(long, conflicts_with = "from_file")]
+        json_stdin: bool,
+    },
+
+    /// Apply the policy defaults to a user's slice (e.g. from a PAM or login hook)
+    ApplyDefaults {
+        /// Username or UID of the target user
+        #[arg(
+            long,
+            required_unless_present = "all_users",
+            conflicts_with = "all_users"
+        )]
+        user: Option<String>,
+
+        /// Apply defaults to every login account in /etc/passwd (UID >= 1000)
+        #[arg(long)]
+        all_users: bool,
+    },
+
+    /// Set capacity aside every day during a time window (e.g. for a nightly batch job)
+    ScheduleHold {
+        /// Number of CPUs to hold (1-1000)
+        #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_CPU as u64..=MAX_CPU as u64))]
+        cpu: u32,
+
+        /// Amount of memory in GB to hold (1-10000)
+        #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(MIN_MEM as u64..=MAX_MEM as u64))]
+        mem: u32,
+
+        /// Local start time, HH:MM (24-hour)
+        #[arg(long, value_parser = parse_time_of_day)]
+        from: String,
+
+        /// Local end time, HH:MM (24-hour); may be earlier than --from to cross midnight
+        #[arg(long, value_parser = parse_time_of_day)]
+        until: String,
+    },
+
+    /// Revert allocations left on the slices of users that no longer exist
+    Prune {
+        /// List the slices that would be reverted without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Check a 24-hour "HH:MM" time for `admin schedule-hold`
+pub fn parse_time_of_day(value: &str) -> Result<String, String> {
+    crate::policy::minutes_of_day(value)
+        .map(|_| value.to_string())
+        .ok_or_else(|| format!("'{}' must be a 24-hour HH:MM time (e.g. 02:00)", value))
+}
+
+/// Presets for `admin setup --profile`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SetupProfile {
+    /// Single main user: half the machine by default, small system reserve
+    Workstation,
+    /// Batch/compute host: tiny default, large system reserve, users request what they need
+    Hpc,
+    /// Several interactive users: a quarter of the machine each, small system reserve
+    Shared,
+}
+
+/// Border styles for `status --table-style`; all but `full` are plain ASCII
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TableStyle {
+    Full,
+    Ascii,
+    Minimal,
+    None,
+}
+
+/// Sort keys for `status --sort`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum StatusSort {
+    Cpu,
+    Mem,
+    Uid,
+    User,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mem_size() {
+        assert_eq!(parse_mem_size("16"), Ok(MemSize::Gb(16.0)));
+        assert_eq!(parse_mem_size("1.5"), Ok(MemSize::Gb(1.5)));
+        assert_eq!(parse_mem_size("2.25"), Ok(MemSize::Gb(2.25)));
+        assert_eq!(parse_mem_size("10000"), Ok(MemSize::Gb(10000.0)));
+        assert_eq!(parse_mem_size("50%"), Ok(MemSize::Percent(50)));
+        assert_eq!(parse_mem_size("100%"), Ok(MemSize::Percent(100)));
+
+        assert!(parse_mem_size("0").is_err());
+        assert!(parse_mem_size("10001").is_err());
+        assert!(parse_mem_size("10000.5").is_err());
+        assert!(parse_mem_size("0.5").is_err());
+        assert!(parse_mem_size("NaN").is_err());
+        assert!(parse_mem_size("inf").is_err());
+        assert!(parse_mem_size("0%").is_err());
+        assert!(parse_mem_size("150%").is_err());
+        assert!(parse_mem_size("half").is_err());
+        assert!(parse_mem_size("%").is_err());
+    }
+
+    #[test]
+    fn test_mem_size_resolve() {
+        assert_eq!(MemSize::Percent(50).resolve(16.0), 8.0);
+        assert_eq!(MemSize::Gb(12.0).resolve(16.0), 12.0);
+        assert_eq!(MemSize::Gb(1.5).resolve(16.0), 1.5);
+        // Rounded down, never below 1
+        assert_eq!(MemSize::Percent(33).resolve(10.0), 3.0);
+        assert_eq!(MemSize::Percent(1).resolve(16.0), 1.0);
+    }
+
+    #[test]
+    fn test_parse_simulated_request() {
+        assert_eq!(
+            parse_simulated_request("alice:8:16"),
+            Ok(SimulatedRequest {
+                user: "alice".to_string(),
+                cpu: 8,
+                mem: 16
+            })
+        );
+        assert_eq!(parse_simulated_request("1001:1:1").unwrap().user, "1001");
+    }
+
+    #[test]
+    fn test_parse_simulated_request_invalid() {
+        assert!(parse_simulated_request("alice:8").is_err());
+        assert!(parse_simulated_request("alice:8:16:4").is_err());
+        assert!(parse_simulated_request(":8:16").is_err());
+        assert!(parse_simulated_request("alice:x:16").is_err());
+        assert!(parse_simulated_request("alice:0:16").is_err());
+        assert!(parse_simulated_request("alice:8:20000").is_err());
+    }
+
+    #[test]
+    fn test_parse_cpu_period_units() {
+        assert_eq!(parse_cpu_period("10ms"), Ok(Duration::from_millis(10)));
+        assert_eq!(parse_cpu_period("1500us"), Ok(Duration::from_micros(1500)));
+        assert_eq!(parse_cpu_period("1s"), Ok(Duration::from_secs(1)));
+        assert_eq!(parse_cpu_period("1ms"), Ok(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn test_parse_cpu_period_out_of_range() {
+        assert!(parse_cpu_period("999us").is_err());
+        assert!(parse_cpu_period("0ms").is_err());
+        assert!(parse_cpu_period("2s").is_err());
+        assert!(parse_cpu_period("1001ms").is_err());
+    }
+
+    #[test]
+    fn test_parse_grace() {
+        assert_eq!(parse_grace("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_grace("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_grace("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_grace("0"), Ok(Duration::ZERO));
+        assert_eq!(parse_grace("60m"), Ok(MAX_GRACE));
+
+        assert!(parse_grace("61m").is_err());
+        assert!(parse_grace("10ms").is_err());
+        assert!(parse_grace("s").is_err());
+        assert!(parse_grace("-5s").is_err());
+        assert!(parse_grace("").is_err());
+    }
+
+    #[test]
+    fn test_request_all_art_is_opt_in() {
+        // Off by default, even on a terminal
+        assert!(!show_request_all_art(true, false, false, true));
+        assert!(show_request_all_art(true, true, false, true));
+
+        // --fun still stays quiet in pipes and --quiet mode
+        assert!(!show_request_all_art(true, true, false, false));
+        assert!(!show_request_all_art(true, true, true, true));
+        assert!(!show_request_all_art(false, true, false, true));
+    }
+
+    #[test]
+    fn test_parse_cpu_period_invalid() {
+        assert!(parse_cpu_period("10").is_err());
+        assert!(parse_cpu_period("ms").is_err());
+        assert!(parse_cpu_period("10min").is_err());
+        assert!(parse_cpu_period("1.5ms").is_err());
+        assert!(parse_cpu_period("").is_err());
+    }
 }
@@ -0,0 +1,829 @@
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+
+use crate::policy::{load_policy_from, read_policy, validate_reserves, PolicyConfig};
+use crate::system::{
+    calculate_available_resources, check_request, check_request_keeping, get_active_user_count,
+    get_login_users, get_system_totals, get_user_allocations, get_user_allocations_aggregate,
+    has_custom_allocation, parse_slice_show, run_systemctl, AllocationLimits, SystemTotals,
+    UserAlloc,
+};
+use crate::systemd::{
+    acquire_allocation_lock, apply_slice_limits, release_if_allocated, revert_user_limits,
+    validate_limits, validate_user_uid, AllocationLock, ReleaseOutcome, SliceOptions,
+};
+
+/// Host queries the allocation math depends on. The real implementation asks
+/// systemctl, logind and /etc/passwd; tests substitute fixed answers.
+pub trait SystemdBackend {
+    /// Current CPU/memory/disk limits of every user slice
+    fn user_allocations(&self) -> io::Result<Vec<UserAlloc>>;
+
+    /// The same limits gathered with as few host queries as possible, for
+    /// callers that only need sums
+    fn user_allocations_aggregate(&self) -> io::Result<Vec<UserAlloc>> {
+        self.user_allocations()
+    }
+
+    /// Number of distinct users with an active session
+    fn active_user_count(&self) -> usize;
+
+    /// UIDs of regular login accounts
+    fn login_uids(&self) -> Vec<u32>;
+
+    /// `systemctl show -p MemoryMax -p CPUQuotaPerSecUSec` output for one user slice
+    fn show_user_slice(&self, uid: u32) -> io::Result<String>;
+
+    /// Set CPUQuota and MemoryMax on one user slice
+    fn set_slice_limits(&self, uid: u32, cpu: u32, mem_gb: u32) -> io::Result<()>;
+
+    /// Revert one user slice (and its disk quota) to the defaults
+    fn revert_slice(&self, uid: u32) -> io::Result<()>;
+
+    /// Block until no other fairshare process can change allocations; held from
+    /// an availability check until the change it allowed has been applied
+    fn lock_allocations(&self) -> io::Result<AllocationLock>;
+}
+
+/// Backend for the running system. The policy supplies the disk partition
+/// and systemctl timeout; without one the defaults (/home, 30s) are used.
+#[derive(Default)]
+pub struct SystemctlBackend {
+    pub policy: Option<PolicyConfig>,
+}
+
+impl SystemctlBackend {
+    pub fn new(policy: Option<PolicyConfig>) -> Self {
+        SystemctlBackend { policy }
+    }
+}
+
+impl SystemdBackend for SystemctlBackend {
+    fn user_allocations(&self) -> io::Result<Vec<UserAlloc>> {
+        get_user_allocations(self.policy.as_ref())
+    }
+
+    fn user_allocations_aggregate(&self) -> io::Result<Vec<UserAlloc>> {
+        get_user_allocations_aggregate(self.policy.as_ref())
+    }
+
+    fn active_user_count(&self) -> usize {
+        get_active_user_count()
+    }
+
+    fn login_uids(&self) -> Vec<u32> {
+        get_login_users()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(_, uid)| uid)
+            .collect()
+    }
+
+    fn show_user_slice(&self, uid: u32) -> io::Result<String> {
+        let output = run_systemctl(&[
+            "show",
+            &format!("user-{}.slice", uid),
+            "-p",
+            "MemoryMax",
+            "-p",
+            "CPUQuotaPerSecUSec",
+        ])?;
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn set_slice_limits(&self, uid: u32, cpu: u32, mem_gb: u32) -> io::Result<()> {
+        apply_slice_limits(uid, cpu, mem_gb as f64, &SliceOptions::default())
+    }
+
+    fn revert_slice(&self, uid: u32) -> io::Result<()> {
+        revert_user_limits(uid, self.policy.as_ref())
+    }
+
+    fn lock_allocations(&self) -> io::Result<AllocationLock> {
+        acquire_allocation_lock()
+    }
+}
+
+/// One user's current limits in a stable, serializable form for tools that
+/// link fairshare as a library (e.g. login MOTD generators)
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AllocationSummary {
+    pub uid: u32,
+    /// CPU limit in cores (0 when unlimited)
+    pub cpu_cores: f64,
+    /// Memory limit in bytes (0 when unlimited)
+    pub mem_bytes: u64,
+    /// Whether the limits are the policy default rather than a requested allocation
+    pub is_default: bool,
+}
+
+/// Machine totals and current allocations, as returned by `FairshareContext::status`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SystemStatus {
+    pub total_cpu: usize,
+    pub total_mem_gb: f64,
+    pub allocations: Vec<AllocationSummary>,
+    /// CPUs and GB of memory a user without an allocation could still request
+    pub available_cpu: u32,
+    pub available_mem_gb: u32,
+}
+
+/// Everything a fairshare command needs to reason about allocations: where the
+/// policy lives, how to query the host, and the machine's totals. Built once in
+/// main and passed to the operations.
+pub struct FairshareContext {
+    pub config_path: PathBuf,
+    pub backend: Box<dyn SystemdBackend>,
+    pub totals: SystemTotals,
+}
+
+impl FairshareContext {
+    pub fn new(
+        config_path: PathBuf,
+        backend: Box<dyn SystemdBackend>,
+        totals: SystemTotals,
+    ) -> Self {
+        FairshareContext {
+            config_path,
+            backend,
+            totals,
+        }
+    }
+
+    /// Context for the running system using the policy at `config_path`
+    pub fn from_system(config_path: PathBuf) -> Self {
+        let policy = load_policy_from(&config_path).ok();
+        let totals = get_system_totals(policy.as_ref());
+        FairshareContext::new(config_path, Box::new(SystemctlBackend::new(policy)), totals)
+    }
+
+    /// The policy at `config_path` (with its policy.d drop-ins). Ok(None) when
+    /// there is no policy; a policy that exists but doesn't parse is an error.
+    pub fn policy(&self) -> io::Result<Option<PolicyConfig>> {
+        read_policy(&self.config_path)
+    }
+
+    /// Why the policy at `config_path` is unusable: it fails to parse, its defaults
+    /// exceed its caps, or its reserves exceed this machine. None if it's fine or
+    /// there is no policy at all.
+    pub fn policy_error(&self) -> Option<io::Error> {
+        match load_policy_from(&self.config_path) {
+            Ok(policy) => validate_reserves(&policy.defaults, &self.totals).err(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => Some(e),
+        }
+    }
+
+    /// The policy's per-user default (cpu, mem, disk), None without a policy
+    pub fn policy_defaults(&self) -> Option<(u32, u32, u32)> {
+        self.policy()
+            .ok()
+            .flatten()
+            .map(|p| (p.defaults.cpu, p.defaults.mem, p.defaults.disk))
+    }
+
+    /// Reserves, defaults and fair share settings from the policy and backend.
+    /// An unreadable policy counts as none here, so commands that act on the
+    /// result check `policy()` first, as `allocate` does.
+    pub fn limits(&self) -> AllocationLimits {
+        limits_with(self.backend.as_ref(), self.policy().ok().flatten().as_ref())
+    }
+
+    pub fn user_allocations(&self) -> io::Result<Vec<UserAlloc>> {
+        self.backend.user_allocations()
+    }
+
+    /// See `system::calculate_available_resources`
+    pub fn available_resources(
+        &self,
+        allocations: &[UserAlloc],
+        requesting_user_uid: Option<&str>,
+    ) -> (u32, u32, u32) {
+        calculate_available_resources(
+            &self.totals,
+            allocations,
+            requesting_user_uid,
+            &self.limits(),
+        )
+    }
+
+    /// Current limits of `uid`'s slice, compared against the policy default
+    pub fn allocation_summary(&self, uid: u32) -> io::Result<AllocationSummary> {
+        let show = self.backend.show_user_slice(uid)?;
+        let alloc = parse_slice_show(&uid.to_string(), &show, 0);
+        let defaults = self
+            .policy()?
+            .map(|p| (p.defaults.cpu, p.defaults.mem, p.defaults.disk));
+
+        Ok(AllocationSummary {
+            uid,
+            cpu_cores: alloc.cpu_quota / 100.0,
+            mem_bytes: alloc.mem_bytes,
+            is_default: !has_custom_allocation(Some(&alloc), defaults),
+        })
+    }
+
+    /// See `allocate_with`. An unreadable policy is an `InvalidPolicy` error
+    /// rather than an allocation without reserves.
+    pub fn allocate(&self, uid: u32, cpu: u32, mem_gb: u32) -> Result<(), FairshareError> {
+        let policy = self.policy().map_err(FairshareError::InvalidPolicy)?;
+        allocate_with(
+            self.backend.as_ref(),
+            policy.as_ref(),
+            &self.totals,
+            uid,
+            cpu,
+            mem_gb,
+        )
+    }
+
+    /// See `release_with`
+    pub fn release(&self, uid: u32) -> Result<ReleaseOutcome, FairshareError> {
+        let policy = self.policy().map_err(FairshareError::InvalidPolicy)?;
+        release_with(self.backend.as_ref(), policy.as_ref(), uid)
+    }
+
+    /// See `status_with`
+    pub fn status(&self) -> Result<SystemStatus, FairshareError> {
+        let policy = self.policy().map_err(FairshareError::InvalidPolicy)?;
+        status_with(self.backend.as_ref(), policy.as_ref(), &self.totals)
+    }
+
+    /// See `system::check_request`
+    pub fn check_request(
+        &self,
+        allocations: &[UserAlloc],
+        req_cpu: u32,
+        req_mem_gb: &str,
+        req_disk_gb: u32,
+        requesting_user_uid: Option<&str>,
+    ) -> Result<(), Option<String>> {
+        check_request(
+            &self.totals,
+            allocations,
+            req_cpu,
+            req_mem_gb,
+            req_disk_gb,
+            requesting_user_uid,
+            &self.limits(),
+        )
+    }
+
+    /// See `system::check_request_keeping`
+    pub fn check_request_keeping(
+        &self,
+        allocations: &[UserAlloc],
+        req_cpu: u32,
+        req_mem_gb: &str,
+        req_disk_gb: u32,
+        requesting_user_uid: Option<&str>,
+        held: (u32, u32),
+    ) -> Result<(), Option<String>> {
+        check_request_keeping(
+            &self.totals,
+            allocations,
+            req_cpu,
+            req_mem_gb,
+            req_disk_gb,
+            requesting_user_uid,
+            &self.limits(),
+            held,
+        )
+    }
+}
+
+/// Why `allocate`, `release` or `status` failed
+#[derive(Debug)]
+pub enum FairshareError {
+    /// The UID, CPU count or memory size is outside what fairshare accepts
+    InvalidRequest(String),
+    /// The request doesn't fit in what is left after reserves and other users
+    InsufficientResources,
+    /// The policy refuses the request whatever is free (memory per CPU ratio,
+    /// dynamic fair share ceiling); carries the explanation
+    Refused(String),
+    /// The policy file exists but can't be read or parsed
+    InvalidPolicy(io::Error),
+    /// Querying or changing the host failed
+    Io(io::Error),
+}
+
+impl std::fmt::Display for FairshareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FairshareError::InvalidRequest(msg) => write!(f, "{}", msg),
+            FairshareError::InsufficientResources => {
+                write!(f, "Request exceeds available system resources")
+            }
+            FairshareError::Refused(reason) => write!(f, "Request refused: {}", reason),
+            FairshareError::InvalidPolicy(e) => write!(f, "Unreadable policy: {}", e),
+            FairshareError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FairshareError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FairshareError::InvalidPolicy(e) | FairshareError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FairshareError {
+    fn from(e: io::Error) -> Self {
+        FairshareError::Io(e)
+    }
+}
+
+/// Reserves, defaults and fair share settings from `policy` and `backend`
+fn limits_with(backend: &dyn SystemdBackend, policy: Option<&PolicyConfig>) -> AllocationLimits {
+    AllocationLimits::new(policy, backend.login_uids(), || backend.active_user_count())
+}
+
+/// Give `uid` `cpu` CPUs and `mem_gb` GB, after the same bounds and
+/// availability checks `fairshare request` applies. A request that doesn't
+/// fit fails with `InsufficientResources` and leaves the slice untouched.
+pub fn allocate_with(
+    backend: &dyn SystemdBackend,
+    policy: Option<&PolicyConfig>,
+    totals: &SystemTotals,
+    uid: u32,
+    cpu: u32,
+    mem_gb: u32,
+) -> Result<(), FairshareError> {
+    validate_user_uid(uid)
+        .and_then(|()| validate_limits(cpu, mem_gb as f64, 0))
+        .map_err(|e| FairshareError::InvalidRequest(e.to_string()))?;
+
+    let _lock = backend.lock_allocations()?;
+    let allocations = backend.user_allocations()?;
+    let uid_str = uid.to_string();
+    check_request(
+        totals,
+        &allocations,
+        cpu,
+        &mem_gb.to_string(),
+        0,
+        Some(&uid_str),
+        &limits_with(backend, policy),
+    )
+    .map_err(|reason| match reason {
+        Some(reason) => FairshareError::Refused(reason),
+        None => FairshareError::InsufficientResources,
+    })?;
+
+    Ok(backend.set_slice_limits(uid, cpu, mem_gb)?)
+}
+
+/// Revert `uid`'s slice to the defaults if they hold a custom allocation
+pub fn release_with(
+    backend: &dyn SystemdBackend,
+    policy: Option<&PolicyConfig>,
+    uid: u32,
+) -> Result<ReleaseOutcome, FairshareError> {
+    validate_user_uid(uid).map_err(|e| FairshareError::InvalidRequest(e.to_string()))?;
+
+    let _lock = backend.lock_allocations()?;
+    let allocations = backend.user_allocations()?;
+    let defaults = policy.map(|p| (p.defaults.cpu, p.defaults.mem, p.defaults.disk));
+    Ok(release_if_allocated(uid, &allocations, defaults, |uid| {
+        backend.revert_slice(uid)
+    })?)
+}
+
+/// Totals, every user slice's limits, and what is left for a new request
+pub fn status_with(
+    backend: &dyn SystemdBackend,
+    policy: Option<&PolicyConfig>,
+    totals: &SystemTotals,
+) -> Result<SystemStatus, FairshareError> {
+    let allocations = backend.user_allocations()?;
+    let defaults = policy.map(|p| (p.defaults.cpu, p.defaults.mem, p.defaults.disk));
+    let (available_cpu, available_mem_gb, _) =
+        calculate_available_resources(totals, &allocations, None, &limits_with(backend, policy));
+
+    Ok(SystemStatus {
+        total_cpu: totals.total_cpu,
+        total_mem_gb: totals.total_mem_gb,
+        allocations: allocations
+            .iter()
+            .filter_map(|a| {
+                Some(AllocationSummary {
+                    uid: a.uid.parse().ok()?,
+                    cpu_cores: a.cpu_quota / 100.0,
+                    mem_bytes: a.mem_bytes,
+                    is_default: !has_custom_allocation(Some(a), defaults),
+                })
+            })
+            .collect(),
+        available_cpu,
+        available_mem_gb,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::parse_policy;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct MockBackend {
+        allocations: Vec<UserAlloc>,
+        active_users: usize,
+        login_uids: Vec<u32>,
+        show_output: String,
+        /// set_slice_limits/revert_slice calls, shared so tests can inspect
+        /// them after the backend is boxed into a context
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl SystemdBackend for MockBackend {
+        fn user_allocations(&self) -> io::Result<Vec<UserAlloc>> {
+            Ok(self.allocations.clone())
+        }
+
+        fn active_user_count(&self) -> usize {
+            self.active_users
+        }
+
+        fn login_uids(&self) -> Vec<u32> {
+            self.login_uids.clone()
+        }
+
+        fn show_user_slice(&self, _uid: u32) -> io::Result<String> {
+            Ok(self.show_output.clone())
+        }
+
+        fn set_slice_limits(&self, uid: u32, cpu: u32, mem_gb: u32) -> io::Result<()> {
+            self.calls
+                .borrow_mut()
+                .push(format!("set {} cpu={} mem={}", uid, cpu, mem_gb));
+            Ok(())
+        }
+
+        fn revert_slice(&self, uid: u32) -> io::Result<()> {
+            self.calls.borrow_mut().push(format!("revert {}", uid));
+            Ok(())
+        }
+
+        fn lock_allocations(&self) -> io::Result<AllocationLock> {
+            crate::systemd::acquire_allocation_lock_at(
+                &std::env::temp_dir()
+                    .join(format!("fairshare-context-lock-{}", std::process::id())),
+            )
+        }
+    }
+
+    fn alloc(uid: &str, cpus: f64, mem_gb: u64) -> UserAlloc {
+        UserAlloc {
+            uid: uid.to_string(),
+            cpu_quota: cpus * 100.0,
+            mem_bytes: mem_gb * 1_000_000_000,
+            disk_bytes: 0,
+        }
+    }
+
+    fn test_context(name: &str, policy: Option<&str>, backend: MockBackend) -> FairshareContext {
+        let dir =
+            std::env::temp_dir().join(format!("fairshare-context-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("policy.toml");
+        if let Some(policy) = policy {
+            fs::write(&path, policy).unwrap();
+        }
+
+        FairshareContext::new(
+            path,
+            Box::new(backend),
+            SystemTotals {
+                total_mem_gb: 32.0,
+                total_cpu: 16,
+                total_disk_gb: 100.0,
+            },
+        )
+    }
+
+    fn cleanup(ctx: &FairshareContext) {
+        if let Some(dir) = ctx.config_path.parent() {
+            fs::remove_dir_all(dir).ok();
+        }
+    }
+
+    #[test]
+    fn test_check_request_through_context_without_policy() {
+        let ctx = test_context(
+            "nopolicy",
+            None,
+            MockBackend {
+                allocations: vec![alloc("1000", 10.0, 20)],
+                active_users: 1,
+                login_uids: vec![1000, 1001],
+                ..MockBackend::default()
+            },
+        );
+        let allocations = ctx.user_allocations().unwrap();
+
+        // 16 - 10 = 6 CPUs and 32 - 20 = 12 GB left for user 1001
+        assert!(ctx
+            .check_request(&allocations, 6, "12", 0, Some("1001"))
+            .is_ok());
+        assert!(ctx
+            .check_request(&allocations, 7, "12", 0, Some("1001"))
+            .is_err());
+        // User 1000 resizing their own allocation only needs the delta
+        assert!(ctx
+            .check_request(&allocations, 16, "32", 0, Some("1000"))
+            .is_ok());
+        assert_eq!(
+            ctx.available_resources(&allocations, Some("1001")),
+            (6, 12, 100)
+        );
+        assert_eq!(ctx.policy_defaults(), None);
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_check_request_through_context_uses_policy_and_backend() {
+        let ctx = test_context(
+            "policy",
+            Some("[defaults]\ncpu = 2\nmem = 4\ndisk = 0\ncpu_reserve = 4\nmem_reserve = 8\n"),
+            MockBackend {
+                allocations: vec![alloc("1000", 4.0, 8)],
+                active_users: 2,
+                // 1001 has no live slice but still holds the default
+                login_uids: vec![1000, 1001, 1002],
+                ..MockBackend::default()
+            },
+        );
+        let allocations = ctx.user_allocations().unwrap();
+
+        // 16 - 4 reserved - 4 (1000) - 2 (1001) = 6 CPUs for 1002
+        // 32 - 8 reserved - 8 (1000) - 4 (1001) = 12 GB for 1002
+        assert_eq!(
+            ctx.available_resources(&allocations, Some("1002")),
+            (6, 12, 100)
+        );
+        assert!(ctx
+            .check_request(&allocations, 6, "12", 0, Some("1002"))
+            .is_ok());
+        assert!(ctx
+            .check_request(&allocations, 7, "12", 0, Some("1002"))
+            .is_err());
+        assert_eq!(ctx.policy_defaults(), Some((2, 4, 0)));
+        assert_eq!(ctx.limits().reserves, (4, 8, 0));
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_context_dynamic_fair_share_uses_backend_sessions() {
+        let ctx = test_context(
+            "fairshare",
+            Some("[defaults]\ncpu = 0\nmem = 0\ndisk = 0\ndynamic_fair_share = true\n"),
+            MockBackend {
+                allocations: vec![],
+                active_users: 4,
+                login_uids: vec![],
+                ..MockBackend::default()
+            },
+        );
+
+        // 16 CPUs / 4 active users = 4 CPUs each
+        assert_eq!(ctx.limits().fair_share_users, Some(4));
+        assert!(ctx.check_request(&[], 4, "8", 0, Some("1000")).is_ok());
+        // The refusal explains the ceiling instead of printing it
+        let reason = ctx.check_request(&[], 5, "8", 0, Some("1000")).unwrap_err();
+        assert!(
+            reason.is_some_and(|r| r.contains("4 active user(s)")),
+            "expected a fair share explanation"
+        );
+        assert!(matches!(
+            ctx.allocate(1000, 5, 8),
+            Err(FairshareError::Refused(reason)) if reason.contains("4 active user(s)")
+        ));
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_allocation_summary_custom_allocation() {
+        let ctx = test_context(
+            "summary-custom",
+            Some("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n"),
+            MockBackend {
+                show_output: "MemoryMax=8000000000\nCPUQuotaPerSecUSec=4s\n".to_string(),
+                ..MockBackend::default()
+            },
+        );
+
+        assert_eq!(
+            ctx.allocation_summary(1000).unwrap(),
+            AllocationSummary {
+                uid: 1000,
+                cpu_cores: 4.0,
+                mem_bytes: 8_000_000_000,
+                is_default: false,
+            }
+        );
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_allocation_summary_policy_default() {
+        let ctx = test_context(
+            "summary-default",
+            Some("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n"),
+            MockBackend {
+                show_output: "MemoryMax=2000000000\nCPUQuotaPerSecUSec=1s\n".to_string(),
+                ..MockBackend::default()
+            },
+        );
+
+        let summary = ctx.allocation_summary(1000).unwrap();
+        assert!(summary.is_default);
+        assert_eq!(summary.cpu_cores, 1.0);
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_allocation_summary_unlimited_slice_without_policy() {
+        let ctx = test_context(
+            "summary-unlimited",
+            None,
+            MockBackend {
+                show_output: "MemoryMax=infinity\nCPUQuotaPerSecUSec=infinity\n".to_string(),
+                ..MockBackend::default()
+            },
+        );
+
+        let summary = ctx.allocation_summary(1000).unwrap();
+        assert_eq!((summary.cpu_cores, summary.mem_bytes), (0.0, 0));
+        assert!(summary.is_default);
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_allocate_sets_slice_limits() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let ctx = test_context(
+            "allocate",
+            None,
+            MockBackend {
+                allocations: vec![alloc("1000", 10.0, 20)],
+                calls: Rc::clone(&calls),
+                ..MockBackend::default()
+            },
+        );
+
+        ctx.allocate(1001, 6, 12).unwrap();
+        assert_eq!(*calls.borrow(), vec!["set 1001 cpu=6 mem=12"]);
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_allocate_over_capacity_leaves_slice_alone() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let ctx = test_context(
+            "allocate-over",
+            None,
+            MockBackend {
+                allocations: vec![alloc("1000", 10.0, 20)],
+                calls: Rc::clone(&calls),
+                ..MockBackend::default()
+            },
+        );
+
+        let err = ctx.allocate(1001, 7, 12).unwrap_err();
+        assert!(matches!(err, FairshareError::InsufficientResources));
+        assert!(calls.borrow().is_empty());
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_allocate_with_malformed_policy_is_an_error() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let ctx = test_context(
+            "allocate-malformed",
+            Some("[reserves\ncpu = "),
+            MockBackend {
+                calls: Rc::clone(&calls),
+                ..MockBackend::default()
+            },
+        );
+
+        let err = ctx.allocate(1000, 1, 1).unwrap_err();
+        assert!(matches!(err, FairshareError::InvalidPolicy(_)));
+        assert!(matches!(
+            ctx.status(),
+            Err(FairshareError::InvalidPolicy(_))
+        ));
+        assert!(calls.borrow().is_empty());
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_allocate_rejects_invalid_input() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let ctx = test_context(
+            "allocate-invalid",
+            None,
+            MockBackend {
+                calls: Rc::clone(&calls),
+                ..MockBackend::default()
+            },
+        );
+
+        assert!(matches!(
+            ctx.allocate(1000, 0, 4),
+            Err(FairshareError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            ctx.allocate(999, 1, 4),
+            Err(FairshareError::InvalidRequest(_))
+        ));
+        assert!(calls.borrow().is_empty());
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_allocate_and_release_with_borrowed_backend() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let backend = MockBackend {
+            allocations: vec![alloc("1000", 10.0, 20)],
+            calls: Rc::clone(&calls),
+            ..MockBackend::default()
+        };
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+
+        allocate_with(&backend, None, &totals, 1001, 6, 12).unwrap();
+        assert!(matches!(
+            allocate_with(&backend, None, &totals, 1001, 7, 12),
+            Err(FairshareError::InsufficientResources)
+        ));
+        // Fits in what is free but breaks the policy's ratio, whatever is free
+        let policy =
+            parse_policy("[defaults]\ncpu = 0\nmem = 0\ndisk = 0\nmax_mem_per_cpu_gb = 2\n")
+                .unwrap();
+        assert!(matches!(
+            allocate_with(&backend, Some(&policy), &totals, 1001, 1, 4),
+            Err(FairshareError::Refused(reason)) if reason.contains("2G-per-CPU")
+        ));
+        assert_eq!(
+            release_with(&backend, None, 1000).unwrap(),
+            ReleaseOutcome::Released
+        );
+        assert_eq!(
+            *calls.borrow(),
+            vec!["set 1001 cpu=6 mem=12", "revert 1000"]
+        );
+    }
+
+    #[test]
+    fn test_release_reverts_custom_allocation_only() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let ctx = test_context(
+            "release",
+            Some("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n"),
+            MockBackend {
+                allocations: vec![alloc("1000", 4.0, 8), alloc("1001", 1.0, 2)],
+                calls: Rc::clone(&calls),
+                ..MockBackend::default()
+            },
+        );
+
+        assert_eq!(ctx.release(1000).unwrap(), ReleaseOutcome::Released);
+        assert_eq!(ctx.release(1001).unwrap(), ReleaseOutcome::NothingAllocated);
+        assert_eq!(*calls.borrow(), vec!["revert 1000"]);
+        cleanup(&ctx);
+    }
+
+    #[test]
+    fn test_status_reports_allocations_and_availability() {
+        let ctx = test_context(
+            "status",
+            Some("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n"),
+            MockBackend {
+                allocations: vec![alloc("1000", 4.0, 8), alloc("1001", 1.0, 2)],
+                ..MockBackend::default()
+            },
+        );
+
+        let status = ctx.status().unwrap();
+        assert_eq!((status.total_cpu, status.total_mem_gb), (16, 32.0));
+        assert_eq!(status.allocations.len(), 2);
+        assert!(!status.allocations[0].is_default);
+        assert!(status.allocations[1].is_default);
+        // 16 - 4 - 1 = 11 CPUs, 32 - 8 - 2 = 22 GB
+        assert_eq!((status.available_cpu, status.available_mem_gb), (11, 22));
+        cleanup(&ctx);
+    }
+}
@@ -0,0 +1,92 @@
+//! fairshare as a library, for tools that want allocation data without
+//! parsing `fairshare info` output. The `fairshare` binary is built on the
+//! same modules.
+
+pub mod cli;
+pub mod context;
+pub mod policy;
+pub mod system;
+pub mod systemd;
+pub mod top;
+
+use std::io;
+use std::path::Path;
+
+pub use context::{
+    AllocationSummary, FairshareContext, FairshareError, SystemStatus, SystemctlBackend,
+    SystemdBackend,
+};
+pub use systemd::ReleaseOutcome;
+
+/// Current limits of `uid`'s slice and whether they are the policy default.
+/// Takes the UID explicitly, so it doesn't depend on pkexec's environment.
+pub fn get_user_allocation_summary(uid: u32) -> io::Result<AllocationSummary> {
+    FairshareContext::from_system(policy::POLICY_PATH.into()).allocation_summary(uid)
+}
+
+/// Give `uid` `cpu` CPUs and `mem_gb` GB of memory through `backend`, checked
+/// against this machine and the policy at `policy::POLICY_PATH`. See
+/// `context::allocate_with`. An unreadable policy is `FairshareError::InvalidPolicy`.
+pub fn allocate(
+    uid: u32,
+    cpu: u32,
+    mem_gb: u32,
+    backend: &dyn SystemdBackend,
+) -> Result<(), FairshareError> {
+    allocate_under(Path::new(policy::POLICY_PATH), uid, cpu, mem_gb, backend)
+}
+
+fn allocate_under(
+    policy_path: &Path,
+    uid: u32,
+    cpu: u32,
+    mem_gb: u32,
+    backend: &dyn SystemdBackend,
+) -> Result<(), FairshareError> {
+    let policy = policy::read_policy(policy_path).map_err(FairshareError::InvalidPolicy)?;
+    context::allocate_with(
+        backend,
+        policy.as_ref(),
+        &system::get_system_totals(policy.as_ref()),
+        uid,
+        cpu,
+        mem_gb,
+    )
+}
+
+/// Revert `uid`'s custom allocation, if any. See `context::release_with`.
+pub fn release(uid: u32, backend: &dyn SystemdBackend) -> Result<ReleaseOutcome, FairshareError> {
+    let policy = policy::load_policy().map_err(FairshareError::InvalidPolicy)?;
+    context::release_with(backend, policy.as_ref(), uid)
+}
+
+/// Totals, allocations and remaining capacity. See `context::status_with`.
+pub fn status(backend: &dyn SystemdBackend) -> Result<SystemStatus, FairshareError> {
+    let policy = policy::load_policy().map_err(FairshareError::InvalidPolicy)?;
+    context::status_with(
+        backend,
+        policy.as_ref(),
+        &system::get_system_totals(policy.as_ref()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_allocate_with_malformed_policy_is_an_error() {
+        let dir = std::env::temp_dir().join(format!("fairshare-lib-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("policy.toml");
+        fs::write(&path, "[reserves\ncpu = ").unwrap();
+
+        // The policy is read before the backend is touched, so this never
+        // reaches systemctl
+        let result = allocate_under(&path, 1000, 1, 1, &SystemctlBackend::default());
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(matches!(result, Err(FairshareError::InvalidPolicy(_))));
+    }
+}
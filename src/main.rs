@@ -1,12 +1,222 @@
-mod cli;
-mod system;
-mod systemd;
-
-use clap::Parser;
-use cli::{AdminSubcommands, Cli, Commands};
+use clap::{CommandFactory, Parser};
 use colored::*;
-use system::*;
-use systemd::*;
+use fairshare::cli::{self, AdminSubcommands, Cli, Commands};
+use fairshare::context::FairshareContext;
+use fairshare::policy;
+use fairshare::system::{self, *};
+use fairshare::systemd::{self, *};
+use std::time::Duration;
+
+/// Seconds between availability checks for `request --wait`
+const WAIT_POLL_INTERVAL_SECS: u64 = 5;
+
+/// Seconds between checks for exited processes during `release --grace`
+const GRACE_POLL_INTERVAL_SECS: u64 = 1;
+
+/// Exit code when systemd is not the init system
+const EXIT_NO_SYSTEMD: i32 = 3;
+
+/// Exit early with a clear message if systemd isn't running as PID 1
+fn require_systemd() {
+    if !systemd_available() {
+        eprintln!(
+            "{} fairshare requires systemd as PID 1; this system appears to use {}",
+            "✗".red().bold(),
+            detect_init_system()
+        );
+        std::process::exit(EXIT_NO_SYSTEMD);
+    }
+}
+
+/// Take the allocation lock or exit. Every command that changes a slice after an
+/// availability check holds it until the change is applied.
+fn lock_allocations(ctx: &FairshareContext) -> AllocationLock {
+    match ctx.backend.lock_allocations() {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!(
+                "{} Failed to acquire allocation lock: {}",
+                "✗".red().bold(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Whether a `check_request` result fits, first printing the policy limit that
+/// refused it, if any
+fn explain_fit(check: Result<(), Option<String>>) -> bool {
+    match check {
+        Ok(()) => true,
+        Err(reason) => {
+            if let Some(reason) = reason {
+                eprintln!("{} {}", "ℹ".bright_blue().bold(), reason);
+            }
+            false
+        }
+    }
+}
+
+/// Re-read the allocations and check the request against them again, for use
+/// under the allocation lock. Up to `held` (cpu, mem) counts as already granted.
+fn still_fits(
+    ctx: &FairshareContext,
+    cpu: u32,
+    mem: f64,
+    disk: u32,
+    requesting_uid: Option<&str>,
+    held: (u32, u32),
+) -> bool {
+    match ctx.user_allocations() {
+        Ok(allocations) => explain_fit(ctx.check_request_keeping(
+            &allocations,
+            cpu,
+            &mem.to_string(),
+            disk,
+            requesting_uid,
+            held,
+        )),
+        Err(e) => {
+            eprintln!("{} Failed to get user allocations: {}", "✗".red().bold(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The policy for settings with a safe fallback (disk partition, IO weight and
+/// the like). An unreadable policy was already reported at startup.
+fn policy_settings(ctx: &FairshareContext) -> Option<policy::PolicyConfig> {
+    ctx.policy().ok().flatten()
+}
+
+/// Refuse to go on with a policy that exists but can't be parsed: its reserves
+/// would silently read as 0 and the machine would be over-allocated
+fn require_readable_policy(ctx: &FairshareContext) {
+    if let Err(e) = policy::read_policy(&ctx.config_path) {
+        eprintln!(
+            "{} Refusing to continue with an unreadable policy: {}",
+            "✗".red().bold(),
+            e
+        );
+        eprintln!(
+            "  Fix the file, or remove it to run with no reserves (see 'fairshare admin setup')"
+        );
+        std::process::exit(1);
+    }
+}
+
+/// `admin apply --json-stdin`: the `--from-file` batch read as JSON from stdin.
+/// Checks the whole batch, then applies each entry and prints a JSON array of
+/// results. Exits 1 if anything was not applied.
+fn apply_json_batch(ctx: &FairshareContext) {
+    let mut input = String::new();
+    let entries = std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)
+        .and_then(|_| parse_batch_json(&input));
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{} Invalid batch on stdin: {}", "✗".red().bold(), e);
+            std::process::exit(1);
+        }
+    };
+
+    let _lock = lock_allocations(ctx);
+    let batch = resolve_batch_users(&entries, get_uid_from_user_string)
+        .and_then(|batch| Ok((batch, ctx.user_allocations()?)))
+        .map_err(|e| format!("{}; nothing was applied", e));
+
+    let result = |(uid, cpu, mem): (u32, u32, u32), error: Option<String>| BatchResult {
+        uid,
+        cpu,
+        mem,
+        applied: error.is_none(),
+        error,
+    };
+    let results: Vec<BatchResult> = match batch {
+        Ok((batch, allocations)) => {
+            match validate_batch(&ctx.totals, &allocations, &batch, &ctx.limits()) {
+                Ok(()) => batch
+                    .into_iter()
+                    .map(|(uid, cpu, mem)| {
+                        let error =
+                            admin_set_user_limits(uid, cpu, mem, 0, policy_settings(ctx).as_ref())
+                                .err()
+                                .map(|e| e.to_string());
+                        result((uid, cpu, mem), error)
+                    })
+                    .collect(),
+                // Nothing is applied unless the whole batch fits
+                Err(rejections) => batch
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, entry)| {
+                        let reasons: Vec<&str> = rejections
+                            .iter()
+                            .filter(|r| r.index == index)
+                            .map(|r| r.reason.as_str())
+                            .collect();
+                        let error = if reasons.is_empty() {
+                            "not applied: another entry was rejected".to_string()
+                        } else {
+                            reasons.join("; ")
+                        };
+                        result(entry, Some(error))
+                    })
+                    .collect(),
+            }
+        }
+        Err(msg) => entries
+            .iter()
+            .map(|(user, cpu, mem)| {
+                result(
+                    (user.parse().unwrap_or_default(), *cpu, *mem),
+                    Some(msg.clone()),
+                )
+            })
+            .collect(),
+    };
+
+    match serde_json::to_string_pretty(&results) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            eprintln!("{} Failed to write results: {}", "✗".red().bold(), e);
+            std::process::exit(1);
+        }
+    }
+    if results.iter().any(|r| !r.applied) {
+        std::process::exit(1);
+    }
+}
+
+/// UIDs of user slices that should take a new default immediately, decided
+/// from the current policy and allocations before they are changed
+fn running_slices_following_default(ctx: &FairshareContext, overrides_reverted: bool) -> Vec<u32> {
+    match ctx.user_allocations() {
+        Ok(allocations) => {
+            slices_following_default(&allocations, ctx.policy_defaults(), overrides_reverted)
+        }
+        Err(e) => {
+            eprintln!(
+                "{} Could not list running user slices, skipping --apply-to-running: {}",
+                "⚠".bright_yellow().bold(),
+                e
+            );
+            vec![]
+        }
+    }
+}
+
+fn push_default_to_running(uids: &[u32], cpu: u32, mem: u32, quiet: bool) {
+    let updated = apply_default_to_running(uids, cpu, mem);
+    if !quiet {
+        println!(
+            "{} Applied the new default to {} running user slice(s)",
+            "✓".green().bold(),
+            updated.to_string().bright_yellow()
+        );
+    }
+}
 
 /// Print the "request all" ASCII art
 fn print_request_all_art() {
@@ -95,28 +305,97 @@ fn print_request_all_art() {
 
 fn main() {
     let cli = Cli::parse();
+    // Under pkexec the binary runs as root on behalf of a regular user, who must not
+    // be able to swap in their own policy (and with it, their own reserves and caps)
+    if cli.config.is_some() && std::env::var("PKEXEC_UID").is_ok_and(|uid| uid.trim() != "0") {
+        eprintln!(
+            "{} {}",
+            "✗".red().bold(),
+            "--config can only be used by root".red()
+        );
+        std::process::exit(1);
+    }
+    if cli.debug {
+        tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(std::io::stderr)
+            .with_ansi(std::io::IsTerminal::is_terminal(&std::io::stderr()))
+            .init();
+    }
+    let ctx = FairshareContext::from_system(
+        cli.config
+            .clone()
+            .unwrap_or_else(|| policy::POLICY_PATH.into()),
+    );
+    if let Some(e) = ctx.policy_error() {
+        eprintln!(
+            "{} Problem with policy {}: {}",
+            "⚠".bright_yellow().bold(),
+            ctx.config_path.display(),
+            e
+        );
+    }
 
     match &cli.command {
-        Commands::Status => {
-            let totals = get_system_totals();
-            let allocations = match get_user_allocations() {
+        Commands::Status {
+            sort,
+            totals_only,
+            table_style,
+        } => {
+            require_systemd();
+            require_readable_policy(&ctx);
+            let allocations = if *totals_only {
+                ctx.backend.user_allocations_aggregate()
+            } else {
+                ctx.user_allocations()
+            };
+            let allocations = match allocations {
                 Ok(allocs) => allocs,
                 Err(e) => {
                     eprintln!("{} Failed to get user allocations: {}", "✗".red().bold(), e);
                     std::process::exit(1);
                 }
             };
-            print_status(&totals, &allocations);
+            if *totals_only {
+                print_status_overview(&ctx.totals, &allocations, &ctx.limits(), *table_style);
+            } else {
+                print_status(
+                    &ctx.totals,
+                    &allocations,
+                    &ctx.limits(),
+                    *sort,
+                    *table_style,
+                );
+            }
         }
 
         Commands::Request {
             cpu,
             mem,
+            cpu_pct,
+            mem_pct,
+            mem_min,
+            cpu_max,
+            cpu_guaranteed,
             disk,
             all,
+            fun,
+            interactive,
+            runtime,
+            wait,
+            cpu_period,
+            io_weight,
+            explain,
+            best_effort,
+            min_cpu,
+            min_mem,
+            force,
+            delegate,
+            preview_command,
         } => {
-            let totals = get_system_totals();
-            let allocations = match get_user_allocations() {
+            require_systemd();
+            require_readable_policy(&ctx);
+            let allocations = match ctx.user_allocations() {
                 Ok(allocs) => allocs,
                 Err(e) => {
                     eprintln!("{} Failed to get user allocations: {}", "✗".red().bold(), e);
@@ -127,38 +406,161 @@ fn main() {
             // Get the calling user's UID to check if they have an existing allocation
             let calling_uid = match systemd::get_calling_user_uid() {
                 Ok(uid) => Some(uid.to_string()),
-                Err(_) => None,
+                Err(e) => {
+                    eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
+                    std::process::exit(1);
+                }
             };
 
+            let held = current_holding(&allocations, calling_uid.as_deref());
+            // --all never grants less than the user already holds, so that much
+            // counts as available even when others have oversubscribed the machine
+            let keep = if *all { held } else { (0, 0) };
+
             // Determine actual CPU and memory to request
             let (actual_cpu, actual_mem, actual_disk) = if *all {
                 // Calculate all available resources
-                let (avail_cpu, avail_mem, avail_disk) =
-                    calculate_available_resources(&totals, &allocations, calling_uid.as_deref());
+                let available = ctx.available_resources(&allocations, calling_uid.as_deref());
 
-                if avail_cpu == 0 && avail_mem == 0 {
+                match all_available_request(available, held) {
+                    Ok((cpu, mem, disk)) => (cpu, mem as f64, disk),
+                    Err(msg) => {
+                        eprintln!("{} {}", "✗".red().bold(), msg.red());
+                        std::process::exit(1);
+                    }
+                }
+            } else if *interactive {
+                if !std::io::IsTerminal::is_terminal(&std::io::stdin()) {
                     eprintln!(
                         "{} {}",
                         "✗".red().bold(),
-                        "No resources available to allocate.".red()
+                        "--interactive needs a terminal on stdin.".red()
                     );
                     std::process::exit(1);
                 }
 
-                (avail_cpu, avail_mem, avail_disk)
+                let available = ctx.available_resources(&allocations, calling_uid.as_deref());
+                let wizard = request_wizard(
+                    &mut std::io::stdin().lock(),
+                    &mut std::io::stderr(),
+                    available,
+                    |cpu, mem| {
+                        explain_fit(ctx.check_request(
+                            &allocations,
+                            cpu,
+                            &mem.to_string(),
+                            disk.unwrap_or(0),
+                            calling_uid.as_deref(),
+                        ))
+                    },
+                );
+                match wizard {
+                    Ok(Some((cpu, mem))) => (cpu, mem as f64, disk.unwrap_or(0)),
+                    Ok(None) => {
+                        eprintln!("{} {}", "✗".red().bold(), "Request cancelled.".red());
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
+                        std::process::exit(1);
+                    }
+                }
             } else {
-                // Use the provided CPU and memory values, disk defaults to 0 if not specified
-                (cpu.unwrap(), mem.unwrap(), disk.unwrap_or(0))
+                // Use the provided CPU and memory values (or percentages of the
+                // machine's totals), disk defaults to 0 if not specified
+                let (pct_cpu, pct_mem) = system::percent_of_totals(
+                    &ctx.totals,
+                    cpu_pct.unwrap_or(100),
+                    mem_pct.unwrap_or(100),
+                );
+                (
+                    cpu.or(*cpu_max).unwrap_or(pct_cpu),
+                    mem.map_or(pct_mem as f64, |mem| mem.resolve(ctx.totals.total_mem_gb)),
+                    disk.unwrap_or(0),
+                )
             };
 
-            if !check_request(
-                &totals,
+            let (actual_cpu, actual_mem, actual_disk) = if *best_effort {
+                let requested = (actual_cpu, actual_mem, actual_disk);
+                let available = ctx.available_resources(&allocations, calling_uid.as_deref());
+
+                match best_effort_grant(requested, available, *min_cpu, *min_mem) {
+                    Ok(grant) => {
+                        if grant != requested && !cli.quiet {
+                            println!(
+                                "{} Full request doesn't fit; granting {} CPU(s) and {}G RAM instead.",
+                                "ℹ".bright_blue().bold(),
+                                grant.0,
+                                grant.1
+                            );
+                        }
+                        grant
+                    }
+                    Err(msg) => {
+                        eprintln!("{} {}", "✗".red().bold(), msg.red());
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                (actual_cpu, actual_mem, actual_disk)
+            };
+
+            let cpu_weight = match cpu_guaranteed.map(|g| guaranteed_cpu_weight(g, actual_cpu)) {
+                Some(Ok(weight)) => Some(weight),
+                Some(Err(e)) => {
+                    eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
+                    std::process::exit(1);
+                }
+                None => None,
+            };
+
+            if let Some(Err(e)) = mem_min.map(|min| validate_mem_min(min, actual_mem)) {
+                eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
+                std::process::exit(1);
+            }
+
+            if *delegate {
+                let allowed = policy_settings(&ctx).is_some_and(|p| p.defaults.allow_delegation);
+                if let Err(e) = validate_delegation(allowed) {
+                    eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
+                    std::process::exit(1);
+                }
+            }
+
+            if !*force {
+                let current = calling_uid
+                    .as_deref()
+                    .and_then(|uid| uid.parse().ok())
+                    .and_then(get_slice_memory_current);
+                if let Some(msg) = memory_shrink_error(current, actual_mem) {
+                    eprintln!("{} {}", "✗".red().bold(), msg.red());
+                    std::process::exit(1);
+                }
+            }
+
+            if *explain {
+                let breakdown = policy::compute_availability(
+                    &ctx.totals,
+                    &allocations,
+                    &ctx.limits(),
+                    calling_uid.as_deref(),
+                );
+                print_availability_breakdown(&breakdown, (actual_cpu, actual_mem, actual_disk));
+            }
+
+            let check = ctx.check_request_keeping(
                 &allocations,
                 actual_cpu,
                 &actual_mem.to_string(),
                 actual_disk,
                 calling_uid.as_deref(),
-            ) {
+                keep,
+            );
+            // A policy limit refuses the request however long it waits
+            let refused = matches!(check, Err(Some(_)));
+            let fits = explain_fit(check);
+
+            if !fits && (wait.is_none() || refused) {
                 eprintln!(
                     "{} {}",
                     "✗".red().bold(),
@@ -167,7 +569,110 @@ fn main() {
                 std::process::exit(1);
             }
 
-            if let Err(e) = systemd::set_user_limits(actual_cpu, actual_mem, actual_disk) {
+            if !fits {
+                // Nothing is allocated until the request fits, so Ctrl-C while
+                // waiting leaves the user's existing allocation untouched
+                let timeout = wait.filter(|secs| *secs > 0).map(Duration::from_secs);
+                let waited = wait_for_resources(
+                    |elapsed| {
+                        eprint!(
+                            "\r{} Waiting for {} and {} to free up... ({}s)",
+                            "⏳".bright_yellow(),
+                            format!("{} CPU(s)", actual_cpu).bright_yellow().bold(),
+                            format!("{}G RAM", actual_mem).bright_yellow().bold(),
+                            elapsed.as_secs()
+                        );
+                        std::io::Write::flush(&mut std::io::stderr()).ok();
+
+                        match ctx.user_allocations() {
+                            Ok(allocations) => ctx.check_request_keeping(
+                                &allocations,
+                                actual_cpu,
+                                &actual_mem.to_string(),
+                                actual_disk,
+                                calling_uid.as_deref(),
+                                keep,
+                            ),
+                            Err(_) => Err(None),
+                        }
+                    },
+                    timeout,
+                    Duration::from_secs(WAIT_POLL_INTERVAL_SECS),
+                    std::thread::sleep,
+                );
+                eprintln!();
+
+                match waited {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        eprintln!(
+                            "{} {}",
+                            "✗".red().bold(),
+                            "Timed out waiting for resources to free up.".red()
+                        );
+                        std::process::exit(1);
+                    }
+                    // e.g. more users logged in and lowered the fair share while waiting
+                    Err(reason) => {
+                        eprintln!("{} {}", "✗".red().bold(), reason.red());
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            let opts = SliceOptions {
+                runtime: *runtime,
+                cpu_period: *cpu_period,
+                io_weight: io_weight
+                    .or_else(|| policy_settings(&ctx).and_then(|p| p.defaults.io_weight)),
+                cpu_weight,
+                mem_min: *mem_min,
+                tasks_max: policy_settings(&ctx).and_then(|p| p.defaults.tasks_max),
+                delegate: *delegate,
+            };
+
+            if *preview_command {
+                let uid = calling_uid.as_deref().and_then(|uid| uid.parse().ok());
+                let args = validate_limits(actual_cpu, actual_mem, actual_disk).and_then(|_| {
+                    build_set_property_args(uid.unwrap_or(0), actual_cpu, actual_mem, &opts)
+                });
+                match args {
+                    Ok(args) => println!("systemctl {}", args.join(" ")),
+                    Err(e) => {
+                        eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+
+            // Taken only now so waiting and prompting never hold up other commands.
+            // Held until set-property has run, after re-checking in case another
+            // allocation was made since the allocations above were read.
+            let _lock = lock_allocations(&ctx);
+            if !still_fits(
+                &ctx,
+                actual_cpu,
+                actual_mem,
+                actual_disk,
+                calling_uid.as_deref(),
+                keep,
+            ) {
+                eprintln!(
+                    "{} {}",
+                    "✗".red().bold(),
+                    "Request no longer fits: another allocation was made in the meantime.".red()
+                );
+                std::process::exit(1);
+            }
+
+            if let Err(e) = systemd::set_user_limits(
+                actual_cpu,
+                actual_mem,
+                actual_disk,
+                &opts,
+                policy_settings(&ctx).as_ref(),
+            ) {
                 eprintln!(
                     "{} {}: {}",
                     "✗".red().bold(),
@@ -177,46 +682,212 @@ fn main() {
                 std::process::exit(1);
             }
 
-            println!(
-                "{} Allocated {}, {} and {}.",
-                "✓".green().bold(),
-                format!("{} CPU(s)", actual_cpu).bright_yellow().bold(),
-                format!("{}G RAM", actual_mem).bright_yellow().bold(),
-                format!("{}G Disk", actual_disk).bright_yellow().bold()
-            );
+            if !cli.quiet {
+                // --all from an existing allocation says it was rolled into the total
+                // --all only grants whole GB
+                match all_request_message(held, (actual_cpu, actual_mem as u32)).filter(|_| *all) {
+                    Some(msg) => println!("{} {}", "✓".green().bold(), msg),
+                    None => println!(
+                        "{} Allocated {}, {} and {}.",
+                        "✓".green().bold(),
+                        format!("{} CPU(s)", actual_cpu).bright_yellow().bold(),
+                        format!("{}G RAM", actual_mem).bright_yellow().bold(),
+                        format!("{}G Disk", actual_disk).bright_yellow().bold()
+                    ),
+                }
+            }
 
-            // If --all was used, display the ASCII art
-            if *all {
+            if cli::show_request_all_art(
+                *all,
+                *fun,
+                cli.quiet,
+                std::io::IsTerminal::is_terminal(&std::io::stdout()),
+            ) {
                 println!();
                 print_request_all_art();
             }
         }
 
-        Commands::Release => {
-            if let Err(e) = release_user_limits() {
-                eprintln!(
-                    "{} {}: {}",
-                    "✗".red().bold(),
-                    "Failed to release limits".red(),
-                    e
+        Commands::Release { grace } => {
+            require_systemd();
+            let drain = |uid: u32| {
+                let Some(grace) = *grace else { return };
+                let signalled = terminate_slice_processes(uid);
+                if signalled.is_empty() {
+                    return;
+                }
+                if !cli.quiet {
+                    println!(
+                        "{} Sent SIGTERM to {} process(es); waiting up to {}s for them to exit...",
+                        "⏳".bright_yellow(),
+                        signalled.len(),
+                        grace.as_secs()
+                    );
+                }
+                let exited = wait_for_exit(
+                    || {
+                        let running = slice_pids(uid);
+                        signalled.iter().filter(|pid| running.contains(pid)).count()
+                    },
+                    grace,
+                    Duration::from_secs(GRACE_POLL_INTERVAL_SECS),
+                    std::thread::sleep,
                 );
+                if !exited {
+                    eprintln!(
+                        "{} Some processes were still running after {}s; releasing anyway.",
+                        "⚠".bright_yellow().bold(),
+                        grace.as_secs()
+                    );
+                }
+            };
+            match release_user_limits(policy_settings(&ctx).as_ref(), drain) {
+                Ok(ReleaseOutcome::Released) => {
+                    if !cli.quiet {
+                        println!(
+                            "{} {}",
+                            "✓".green().bold(),
+                            "Released user limits back to defaults.".green()
+                        );
+                    }
+                }
+                Ok(ReleaseOutcome::NothingAllocated) => {
+                    if !cli.quiet {
+                        println!(
+                            "{} {}",
+                            "ℹ".bright_blue().bold(),
+                            "No custom allocation to release.".bright_white()
+                        );
+                    }
+                }
+                Err(e) => {
+                    eprintln!(
+                        "{} {}: {}",
+                        "✗".red().bold(),
+                        "Failed to release limits".red(),
+                        e
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Info { user, porcelain } => {
+            require_systemd();
+            if let Err(e) =
+                show_user_info(user.as_deref(), *porcelain, policy_settings(&ctx).as_ref())
+            {
+                eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
                 std::process::exit(1);
             }
+        }
+
+        Commands::Allocations { json } => {
+            require_systemd();
+            let allocations = match ctx.user_allocations() {
+                Ok(allocs) => allocs,
+                Err(e) => {
+                    eprintln!("{} Failed to get user allocations: {}", "✗".red().bold(), e);
+                    std::process::exit(1);
+                }
+            };
+            if !*json {
+                print_allocations(allocations);
+                return;
+            }
+            match allocations_json(&allocations, get_username_from_uid) {
+                Ok(json) => println!("{}", json),
+                Err(e) => {
+                    eprintln!("{} Failed to write allocations: {}", "✗".red().bold(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Simulate { add } => {
+            require_systemd();
+            let allocations = match ctx.user_allocations() {
+                Ok(allocs) => allocs,
+                Err(e) => {
+                    eprintln!("{} Failed to get user allocations: {}", "✗".red().bold(), e);
+                    std::process::exit(1);
+                }
+            };
+
+            let mut requests = vec![];
+            for req in add {
+                match get_uid_from_user_string(&req.user) {
+                    Ok(uid) => requests.push((uid, req.cpu, req.mem)),
+                    Err(e) => {
+                        eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            let simulated = simulate_allocations(&allocations, &requests);
+            print_status(
+                &ctx.totals,
+                &simulated,
+                &ctx.limits(),
+                cli::StatusSort::Uid,
+                cli::TableStyle::Full,
+            );
+
+            let (cpu, mem, disk) = ctx.available_resources(&simulated, None);
             println!(
-                "{} {}",
-                "✓".green().bold(),
-                "Released user limits back to defaults.".green()
+                "{} Simulated only, nothing was applied. Still requestable: {} CPUs, {}G RAM, {}G Disk",
+                "ℹ".bright_blue().bold(),
+                cpu.to_string().bright_green(),
+                mem.to_string().bright_green(),
+                disk.to_string().bright_green()
             );
         }
 
-        Commands::Info => {
-            if let Err(e) = show_user_info() {
+        Commands::Top { interval } => {
+            require_systemd();
+            if let Err(e) = fairshare::top::run(&ctx.totals, Duration::from_secs(*interval)) {
                 eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Completions { shell } => {
+            clap_complete::generate(
+                *shell,
+                &mut Cli::command(),
+                "fairshare",
+                &mut std::io::stdout(),
+            );
+        }
+
+        Commands::Version { verbose } => {
+            println!("fairshare {}", env!("CARGO_PKG_VERSION"));
+            if *verbose {
+                println!("Commit:  {}", env!("FAIRSHARE_GIT_COMMIT"));
+                println!("Target:  {}", env!("FAIRSHARE_TARGET"));
+                println!("Cgroups: {}", detect_cgroup_version());
+                println!(
+                    "Systemd: {}",
+                    systemd_version().unwrap_or_else(|| "not found".to_string())
+                );
+                println!(
+                    "Glibc:   {}",
+                    glibc_version().unwrap_or_else(|| "not glibc".to_string())
+                );
+            }
+        }
+
+        Commands::Man => {
+            if let Err(e) = cli::render_man_page(&mut std::io::stdout()) {
+                eprintln!("{} Failed to render man page: {}", "✗".red().bold(), e);
+                std::process::exit(1);
             }
         }
 
         Commands::Admin { sub } => match sub {
             AdminSubcommands::Setup {
+                profile,
                 cpu,
                 mem,
                 disk,
@@ -224,15 +895,54 @@ fn main() {
                 mem_reserve,
                 disk_reserve,
                 disk_partition,
+                tasks,
+                print,
+                apply_to_running,
             } => {
+                // Explicit flags override the preset, which overrides the built-in defaults
+                let (preset_cpu, preset_mem, preset_cpu_reserve, preset_mem_reserve) = profile
+                    .map(|p| system::profile_defaults(p, &ctx.totals))
+                    .unwrap_or((1, 2, 2, 4));
+                let cpu = cpu.unwrap_or(preset_cpu);
+                let mem = mem.unwrap_or(preset_mem);
+                let cpu_reserve = cpu_reserve.unwrap_or(preset_cpu_reserve);
+                let mem_reserve = mem_reserve.unwrap_or(preset_mem_reserve);
+
+                if *print {
+                    let preview = setup_file_contents(
+                        cpu,
+                        mem,
+                        *disk,
+                        cpu_reserve,
+                        mem_reserve,
+                        *disk_reserve,
+                        disk_partition.as_deref(),
+                        *tasks,
+                    )
+                    .and_then(|files| {
+                        write_setup_preview(&mut std::io::stdout(), &files, &ctx.config_path)
+                    });
+                    if let Err(e) = preview {
+                        eprintln!("{} {}: {}", "✗".red().bold(), "Setup failed".red(), e);
+                        std::process::exit(1);
+                    }
+                    return;
+                }
+
+                // Which slices follow the default is decided against the old policy
+                let following =
+                    apply_to_running.then(|| running_slices_following_default(&ctx, false));
+
                 if let Err(e) = admin_setup_defaults(
-                    *cpu,
-                    *mem,
+                    cpu,
+                    mem,
                     *disk,
-                    *cpu_reserve,
-                    *mem_reserve,
+                    cpu_reserve,
+                    mem_reserve,
                     *disk_reserve,
                     disk_partition.clone(),
+                    *tasks,
+                    &ctx.config_path,
                 ) {
                     eprintln!("{} {}: {}", "✗".red().bold(), "Setup failed".red(), e);
                     std::process::exit(1);
@@ -245,27 +955,54 @@ fn main() {
                     "Disk=disabled".bright_white().to_string()
                 };
 
-                println!(
-                    "{} Global defaults applied: {} {} {} (Reserves: {} CPUs, {}G RAM, {}G Disk)",
-                    "✓".green().bold(),
-                    format!("CPUQuota={}%", cpu * 100).bright_yellow(),
-                    format!("MemoryMax={}G", mem).bright_yellow(),
-                    disk_msg,
-                    format!("{}", cpu_reserve).bright_cyan(),
-                    format!("{}", mem_reserve).bright_cyan(),
-                    format!("{}", disk_reserve).bright_cyan()
-                );
+                let tasks_msg = tasks
+                    .map(|t| format!(" {}", format!("TasksMax={}", t).bright_yellow()))
+                    .unwrap_or_default();
 
-                if let Some(ref partition) = disk_partition {
+                if !cli.quiet {
                     println!(
-                        "{} Monitored Partition: {}",
-                        "→".bright_white(),
-                        partition.bright_cyan()
+                        "{} Global defaults applied: {} {} {}{} (Reserves: {} CPUs, {}G RAM, {}G Disk)",
+                        "✓".green().bold(),
+                        format!("CPUQuota={}%", cpu * 100).bright_yellow(),
+                        format!("MemoryMax={}G", mem).bright_yellow(),
+                        disk_msg,
+                        tasks_msg,
+                        format!("{}", cpu_reserve).bright_cyan(),
+                        format!("{}", mem_reserve).bright_cyan(),
+                        format!("{}", disk_reserve).bright_cyan()
                     );
+
+                    if let Some(ref partition) = disk_partition {
+                        println!(
+                            "{} Monitored Partition: {}",
+                            "→".bright_white(),
+                            partition.bright_cyan()
+                        );
+                    }
+                }
+
+                if let Some(uids) = following {
+                    push_default_to_running(&uids, cpu, mem, cli.quiet);
                 }
             }
             AdminSubcommands::Uninstall { force } => {
                 if !force {
+                    match ctx.user_allocations() {
+                        Ok(allocations) => {
+                            if let Err(e) = uninstall_interlock(&allocations, ctx.policy_defaults())
+                            {
+                                eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
+                                std::process::exit(1);
+                            }
+                        }
+                        Err(e) => eprintln!(
+                            "{} {}: {}",
+                            "⚠".bright_yellow().bold(),
+                            "Could not check for active allocations".bright_yellow(),
+                            e
+                        ),
+                    }
+
                     eprintln!(
                         "{} {}",
                         "⚠".bright_yellow().bold(),
@@ -273,8 +1010,11 @@ fn main() {
                     );
                     eprintln!("{} ", "  Files to be removed:".bright_white().bold());
                     eprintln!("    - /etc/systemd/system/user-.slice.d/00-defaults.conf");
-                    eprintln!("    - /etc/fairshare/policy.toml");
-                    eprintln!("    - /etc/fairshare/ (if empty)");
+                    let policy_path = &ctx.config_path;
+                    eprintln!("    - {}", policy_path.display());
+                    if let Some(dir) = policy_path.parent() {
+                        eprintln!("    - {}/ (if empty)", dir.display());
+                    }
                     eprint!(
                         "\n{} {}",
                         "Continue?".bright_white().bold(),
@@ -292,16 +1032,18 @@ fn main() {
                     }
                 }
 
-                if let Err(e) = admin_uninstall_defaults() {
+                if let Err(e) = admin_uninstall_defaults(&ctx.config_path) {
                     eprintln!("{} {}: {}", "✗".red().bold(), "Uninstall failed".red(), e);
                     std::process::exit(1);
                 }
-                println!(
-                    "{} {}",
-                    "✓".green().bold(),
-                    "Global defaults uninstalled. System reverted to standard resource limits."
-                        .green()
-                );
+                if !cli.quiet {
+                    println!(
+                        "{} {}",
+                        "✓".green().bold(),
+                        "Global defaults uninstalled. System reverted to standard resource limits."
+                            .green()
+                    );
+                }
             }
             AdminSubcommands::Reset {
                 cpu,
@@ -311,17 +1053,30 @@ fn main() {
                 mem_reserve,
                 disk_reserve,
                 disk_partition,
+                tasks,
+                keep_allocations,
+                apply_to_running,
                 force,
             } => {
                 if !force {
-                    eprintln!(
-                        "{} {}",
-                        "⚠".bright_yellow().bold(),
-                        "This will reset all fairshare defaults and remove active user overrides!"
-                            .bright_yellow()
-                    );
-                    eprintln!("{} ", "  This will:".bright_white().bold());
-                    eprintln!("    - Revert all active user allocations");
+                    if *keep_allocations {
+                        eprintln!(
+                            "{} {}",
+                            "⚠".bright_yellow().bold(),
+                            "This will reset all fairshare defaults!".bright_yellow()
+                        );
+                        eprintln!("{} ", "  This will:".bright_white().bold());
+                        eprintln!("    - Keep all active user allocations");
+                    } else {
+                        eprintln!(
+                            "{} {}",
+                            "⚠".bright_yellow().bold(),
+                            "This will reset all fairshare defaults and remove active user overrides!"
+                                .bright_yellow()
+                        );
+                        eprintln!("{} ", "  This will:".bright_white().bold());
+                        eprintln!("    - Revert all active user allocations");
+                    }
                     eprintln!("    - Remove all fairshare configuration files");
                     let disk_msg = if let Some(d) = disk {
                         format!("{}G Disk", d)
@@ -349,6 +1104,9 @@ fn main() {
                     }
                 }
 
+                let following = apply_to_running
+                    .then(|| running_slices_following_default(&ctx, !*keep_allocations));
+
                 if let Err(e) = admin_reset(
                     *cpu,
                     *mem,
@@ -357,15 +1115,24 @@ fn main() {
                     *mem_reserve,
                     *disk_reserve,
                     disk_partition.clone(),
+                    *tasks,
+                    *keep_allocations,
+                    &ctx.config_path,
                 ) {
                     eprintln!("{} {}: {}", "✗".red().bold(), "Reset failed".red(), e);
                     std::process::exit(1);
                 }
-                println!(
-                    "{} {}",
-                    "✓".green().bold(),
-                    "System limits reset and defaults applied.".green()
-                );
+                if !cli.quiet {
+                    println!(
+                        "{} {}",
+                        "✓".green().bold(),
+                        "System limits reset and defaults applied.".green()
+                    );
+                }
+
+                if let Some(uids) = following {
+                    push_default_to_running(&uids, *cpu, *mem, cli.quiet);
+                }
             }
             AdminSubcommands::SetUser {
                 user,
@@ -388,8 +1155,7 @@ fn main() {
                     .unwrap_or_else(|| format!("UID {}", uid));
 
                 // Check resource availability and warn if exceeding
-                let totals = get_system_totals();
-                let allocations = match get_user_allocations() {
+                let allocations = match ctx.user_allocations() {
                     Ok(allocs) => allocs,
                     Err(e) => {
                         eprintln!("{} Failed to get user allocations: {}", "✗".red().bold(), e);
@@ -398,14 +1164,14 @@ fn main() {
                 };
 
                 // Check if the request exceeds available resources
-                if !check_request(
-                    &totals,
+                let fits = explain_fit(ctx.check_request(
                     &allocations,
                     *cpu,
                     &mem.to_string(),
                     disk.unwrap_or(0),
                     Some(&uid.to_string()),
-                ) {
+                ));
+                if !fits {
                     if !force {
                         eprintln!(
                             "{} {}",
@@ -442,7 +1208,35 @@ fn main() {
                     }
                 }
 
-                if let Err(e) = admin_set_user_limits(uid, *cpu, *mem, disk.unwrap_or(0)) {
+                // An allocation that fit must still fit under the lock; one over
+                // capacity was already confirmed or forced
+                let _lock = lock_allocations(&ctx);
+                if fits
+                    && !still_fits(
+                        &ctx,
+                        *cpu,
+                        *mem as f64,
+                        disk.unwrap_or(0),
+                        Some(&uid.to_string()),
+                        (0, 0),
+                    )
+                {
+                    eprintln!(
+                        "{} {}",
+                        "✗".red().bold(),
+                        "Allocation no longer fits: another allocation was made in the meantime."
+                            .red()
+                    );
+                    std::process::exit(1);
+                }
+
+                if let Err(e) = admin_set_user_limits(
+                    uid,
+                    *cpu,
+                    *mem,
+                    disk.unwrap_or(0),
+                    policy_settings(&ctx).as_ref(),
+                ) {
                     eprintln!(
                         "{} {}: {}",
                         "✗".red().bold(),
@@ -458,14 +1252,271 @@ fn main() {
                 } else {
                     "no disk quota".bright_yellow().bold()
                 };
-                println!(
-                    "{} Allocated {}, {} and {} for user {}.",
-                    "✓".green().bold(),
-                    format!("{} CPU(s)", cpu).bright_yellow().bold(),
-                    format!("{}G RAM", mem).bright_yellow().bold(),
-                    disk_display,
-                    username.bright_cyan()
-                );
+                if !cli.quiet {
+                    println!(
+                        "{} Allocated {}, {} and {} for user {}.",
+                        "✓".green().bold(),
+                        format!("{} CPU(s)", cpu).bright_yellow().bold(),
+                        format!("{}G RAM", mem).bright_yellow().bold(),
+                        disk_display,
+                        username.bright_cyan()
+                    );
+                }
+            }
+            AdminSubcommands::Apply {
+                from_file: Some(from_file),
+                ..
+            } => {
+                let batch = std::fs::read_to_string(from_file)
+                    .and_then(|contents| parse_batch_file(&contents))
+                    .and_then(|entries| {
+                        resolve_batch_users(&entries, get_uid_from_user_string)
+                            .map(|uids| (entries, uids))
+                    });
+                let (entries, batch) = match batch {
+                    Ok(batch) => batch,
+                    Err(e) => {
+                        eprintln!(
+                            "{} Invalid batch file {}: {}",
+                            "✗".red().bold(),
+                            from_file.display(),
+                            e
+                        );
+                        std::process::exit(1);
+                    }
+                };
+
+                // Check the whole batch before touching any slice, holding the lock
+                // until every entry is applied
+                let _lock = lock_allocations(&ctx);
+                let allocations = match ctx.user_allocations() {
+                    Ok(allocs) => allocs,
+                    Err(e) => {
+                        eprintln!("{} Failed to get user allocations: {}", "✗".red().bold(), e);
+                        std::process::exit(1);
+                    }
+                };
+                if let Err(rejections) =
+                    validate_batch(&ctx.totals, &allocations, &batch, &ctx.limits())
+                {
+                    for rejection in &rejections {
+                        eprintln!(
+                            "{} {} (UID {}): {}",
+                            "✗".red().bold(),
+                            entries[rejection.index].0.bright_yellow(),
+                            rejection.uid,
+                            rejection.reason.red()
+                        );
+                    }
+                    eprintln!(
+                        "{} {} of {} entries rejected; nothing was applied",
+                        "✗".red().bold(),
+                        rejections.len(),
+                        batch.len()
+                    );
+                    std::process::exit(1);
+                }
+
+                let mut failed = 0;
+                for ((user, _, _), &(uid, cpu, mem)) in entries.iter().zip(&batch) {
+                    match admin_set_user_limits(uid, cpu, mem, 0, policy_settings(&ctx).as_ref()) {
+                        Ok(()) => {
+                            if !cli.quiet {
+                                println!(
+                                    "{} {} (UID {}): {} CPUs, {}G RAM",
+                                    "✓".green().bold(),
+                                    user.bright_yellow(),
+                                    uid,
+                                    cpu,
+                                    mem
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            failed += 1;
+                            eprintln!(
+                                "{} {} (UID {}): {}",
+                                "✗".red().bold(),
+                                user.bright_yellow(),
+                                uid,
+                                e
+                            );
+                        }
+                    }
+                }
+
+                if failed > 0 {
+                    eprintln!(
+                        "{} {} of {} user(s) could not be updated",
+                        "✗".red().bold(),
+                        failed,
+                        batch.len()
+                    );
+                    std::process::exit(1);
+                }
+            }
+            // --json-stdin, the only other way clap accepts `apply`
+            AdminSubcommands::Apply { .. } => apply_json_batch(&ctx),
+            AdminSubcommands::ApplyDefaults { user, all_users } => {
+                let targets = if *all_users {
+                    match system::get_login_users() {
+                        Ok(users) => users,
+                        Err(e) => {
+                            eprintln!(
+                                "{} {}: {}",
+                                "✗".red().bold(),
+                                "Failed to read /etc/passwd".red(),
+                                e
+                            );
+                            std::process::exit(1);
+                        }
+                    }
+                } else {
+                    let user = user.as_deref().unwrap_or_default();
+                    let uid = match system::get_uid_from_user_string(user) {
+                        Ok(uid) => uid,
+                        Err(e) => {
+                            eprintln!("{} {}: {}", "✗".red().bold(), "Invalid user".red(), e);
+                            std::process::exit(1);
+                        }
+                    };
+                    let username = system::get_username_from_uid(&uid.to_string())
+                        .unwrap_or_else(|| format!("UID {}", uid));
+                    vec![(username, uid)]
+                };
+
+                let mut failures = 0;
+                for (username, uid) in &targets {
+                    match admin_apply_defaults(*uid, &ctx.config_path) {
+                        Ok((cpu, mem)) => {
+                            if !cli.quiet {
+                                println!(
+                                    "{} Applied defaults {} and {} for user {}.",
+                                    "✓".green().bold(),
+                                    format!("{} CPU(s)", cpu).bright_yellow().bold(),
+                                    format!("{}G RAM", mem).bright_yellow().bold(),
+                                    username.bright_cyan()
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            failures += 1;
+                            eprintln!(
+                                "{} {} {}: {}",
+                                "✗".red().bold(),
+                                "Failed to apply defaults for user".red(),
+                                username.bright_cyan(),
+                                e
+                            );
+                        }
+                    }
+                }
+
+                if failures > 0 {
+                    std::process::exit(1);
+                }
+            }
+
+            AdminSubcommands::ScheduleHold {
+                cpu,
+                mem,
+                from,
+                until,
+            } => {
+                if let Err(e) = admin_schedule_hold(*cpu, *mem, from, until, &ctx.config_path) {
+                    eprintln!(
+                        "{} {}: {}",
+                        "✗".red().bold(),
+                        "Failed to schedule hold".red(),
+                        e
+                    );
+                    std::process::exit(1);
+                }
+                if !cli.quiet {
+                    println!(
+                        "{} Holding {} and {} daily from {} to {}.",
+                        "✓".green().bold(),
+                        format!("{} CPU(s)", cpu).bright_yellow().bold(),
+                        format!("{}G RAM", mem).bright_yellow().bold(),
+                        from.bright_cyan(),
+                        until.bright_cyan()
+                    );
+                }
+            }
+
+            AdminSubcommands::Prune { dry_run } => {
+                let allocations = match ctx.user_allocations() {
+                    Ok(allocations) => allocations,
+                    Err(e) => {
+                        eprintln!(
+                            "{} {}: {}",
+                            "✗".red().bold(),
+                            "Failed to list user slices".red(),
+                            e
+                        );
+                        std::process::exit(1);
+                    }
+                };
+
+                let uids = prunable_uids(&allocations, system::lookup_username);
+                if uids.is_empty() {
+                    if !cli.quiet {
+                        println!(
+                            "{} No allocations belong to deleted users.",
+                            "✓".green().bold()
+                        );
+                    }
+                    return;
+                }
+
+                if *dry_run {
+                    println!(
+                        "{} Would revert {} slice(s) of deleted users:",
+                        "→".bright_white(),
+                        uids.len().to_string().bright_yellow()
+                    );
+                    for uid in &uids {
+                        println!("    - user-{}.slice", uid);
+                    }
+                    return;
+                }
+
+                let mut failures = 0;
+                for uid in &uids {
+                    match revert_user_limits(*uid, policy_settings(&ctx).as_ref()) {
+                        Ok(()) => {
+                            if !cli.quiet {
+                                println!(
+                                    "{} Reverted limits for deleted UID {}",
+                                    "✓".green().bold(),
+                                    uid.to_string().bright_white()
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            failures += 1;
+                            eprintln!(
+                                "{} {} {}: {}",
+                                "✗".red().bold(),
+                                "Failed to revert limits for UID".red(),
+                                uid.to_string().bright_white(),
+                                e
+                            );
+                        }
+                    }
+                }
+
+                if !cli.quiet {
+                    println!(
+                        "{} Pruned {} of {} allocation(s) of deleted users.",
+                        "→".bright_white(),
+                        (uids.len() - failures).to_string().bright_yellow(),
+                        uids.len()
+                    );
+                }
+                if failures > 0 {
+                    std::process::exit(1);
+                }
             }
         },
     }
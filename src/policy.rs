@@ -0,0 +1,1678 @@
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::cli::{MAX_IO_WEIGHT, MIN_IO_WEIGHT};
+use crate::system::{AllocationLimits, SystemTotals, UserAlloc};
+
+/// Location of the policy file written by `admin setup`
+pub const POLICY_PATH: &str = "/etc/fairshare/policy.toml";
+
+/// Contents of /etc/fairshare/policy.toml
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct PolicyConfig {
+    pub defaults: PolicyDefaults,
+    #[serde(default)]
+    pub max_caps: Option<MaxCaps>,
+    #[serde(default)]
+    pub holds: Vec<ScheduledHold>,
+}
+
+/// Per-user defaults and system reserves (the `[defaults]` table)
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct PolicyDefaults {
+    pub cpu: u32,
+    pub mem: u32,
+    pub disk: u32,
+    #[serde(default)]
+    pub cpu_reserve: u32,
+    #[serde(default)]
+    pub mem_reserve: u32,
+    #[serde(default)]
+    pub disk_reserve: u32,
+    #[serde(default)]
+    pub disk_partition: Option<String>,
+    /// Cap each user's grant at the total divided by the number of logged-in users
+    #[serde(default)]
+    pub dynamic_fair_share: bool,
+    /// Largest memory (GB) a request may ask for per requested CPU; unlimited when unset
+    #[serde(default)]
+    pub max_mem_per_cpu_gb: Option<u32>,
+    /// Seconds a systemctl query may run before it is killed (default 30)
+    #[serde(default)]
+    pub systemctl_timeout_secs: Option<u64>,
+    /// IOWeight set on requests that don't pass --io-weight; left unset when None
+    #[serde(default)]
+    pub io_weight: Option<u32>,
+    /// Percent of all CPUs that must stay unallocated; 0 disables the floor
+    #[serde(default)]
+    pub min_free_cpu_pct: u32,
+    /// Percent of all memory that must stay unallocated; 0 disables the floor
+    #[serde(default)]
+    pub min_free_mem_pct: u32,
+    /// TasksMax written into the user slice defaults; the distro default when unset
+    #[serde(default)]
+    pub tasks_max: Option<u32>,
+    /// How fractional availability becomes whole CPUs and GB (default floor)
+    #[serde(default)]
+    pub rounding: Rounding,
+    /// Let `request --delegate` hand users cgroup delegation of their slice
+    #[serde(default)]
+    pub allow_delegation: bool,
+}
+
+/// The `rounding` key: how available capacity such as 3.9 CPUs is turned into
+/// the whole number shown by `status` and granted by `request --all`
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    /// Never offer more than is free (3.9 -> 3)
+    #[default]
+    Floor,
+    /// Offer the partly free unit too (3.1 -> 4)
+    Ceil,
+    /// Round half away from zero (3.5 -> 4, 3.4 -> 3)
+    Nearest,
+}
+
+impl Rounding {
+    /// `value` as a whole number under this mode, 0 when nothing is free
+    pub fn whole(self, value: f64) -> u32 {
+        if value <= 0.0 {
+            return 0;
+        }
+        match self {
+            Rounding::Floor => value.floor() as u32,
+            Rounding::Ceil => value.ceil() as u32,
+            Rounding::Nearest => value.round() as u32,
+        }
+    }
+}
+
+/// Per-user upper limits (the `[max_caps]` table)
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct MaxCaps {
+    pub cpu: u32,
+    pub mem: u32,
+    #[serde(default)]
+    pub disk: u32,
+}
+
+/// Capacity set aside every day between `from` and `until` (local "HH:MM"), e.g.
+/// for a nightly batch job (the `[[holds]]` array). Windows may cross midnight.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduledHold {
+    pub cpu: u32,
+    pub mem: u32,
+    pub from: String,
+    pub until: String,
+}
+
+impl ScheduledHold {
+    /// Whether the hold applies at `now`, in minutes since local midnight
+    pub fn is_active_at(&self, now: u32) -> bool {
+        match (minutes_of_day(&self.from), minutes_of_day(&self.until)) {
+            (Some(from), Some(until)) => window_contains(from, until, now),
+            _ => false,
+        }
+    }
+}
+
+/// Minutes since midnight for a 24-hour "HH:MM" time
+pub fn minutes_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Whether `now` falls in [from, until), wrapping past midnight when until < from
+fn window_contains(from: u32, until: u32, now: u32) -> bool {
+    if from <= until {
+        from <= now && now < until
+    } else {
+        now >= from || now < until
+    }
+}
+
+/// `contents` with a `[[holds]]` entry appended, checked to still parse
+pub fn append_hold(
+    contents: &str,
+    cpu: u32,
+    mem: u32,
+    from: &str,
+    until: &str,
+) -> io::Result<String> {
+    let updated = format!(
+        "{}\n\n[[holds]]\ncpu = {}\nmem = {}\nfrom = \"{}\"\nuntil = \"{}\"\n",
+        contents.trim_end(),
+        cpu,
+        mem,
+        from,
+        until
+    );
+    parse_policy(&updated)?;
+    Ok(updated)
+}
+
+/// Parse policy.toml contents
+pub fn parse_policy(contents: &str) -> io::Result<PolicyConfig> {
+    let policy = toml::from_str::<PolicyConfig>(contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    if let Some(weight) = policy.defaults.io_weight {
+        if !(MIN_IO_WEIGHT..=MAX_IO_WEIGHT).contains(&weight) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "io_weight {} must be {}-{}",
+                    weight, MIN_IO_WEIGHT, MAX_IO_WEIGHT
+                ),
+            ));
+        }
+    }
+
+    if policy.defaults.tasks_max == Some(0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "tasks_max must be greater than 0",
+        ));
+    }
+
+    for (key, pct) in [
+        ("min_free_cpu_pct", policy.defaults.min_free_cpu_pct),
+        ("min_free_mem_pct", policy.defaults.min_free_mem_pct),
+    ] {
+        if pct > 100 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} {} must be 0-100", key, pct),
+            ));
+        }
+    }
+
+    for hold in &policy.holds {
+        let from = minutes_of_day(&hold.from);
+        let until = minutes_of_day(&hold.until);
+        if from.is_none() || until.is_none() || from == until {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "hold {}-{} needs two different HH:MM times",
+                    hold.from, hold.until
+                ),
+            ));
+        }
+    }
+
+    validate_policy_consistency(&policy)?;
+
+    Ok(policy)
+}
+
+/// Check that the `[defaults]` grant doesn't exceed `[max_caps]`. A disk cap of 0
+/// means no disk cap.
+pub fn validate_policy_consistency(policy: &PolicyConfig) -> io::Result<()> {
+    let Some(caps) = &policy.max_caps else {
+        return Ok(());
+    };
+
+    let defaults = &policy.defaults;
+    for (key, default, cap) in [
+        ("cpu", defaults.cpu, caps.cpu),
+        ("mem", defaults.mem, caps.mem),
+        (
+            "disk",
+            defaults.disk,
+            if caps.disk == 0 { u32::MAX } else { caps.disk },
+        ),
+    ] {
+        if default > cap {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "[defaults] {} = {} exceeds [max_caps] {} = {}",
+                    key, default, key, cap
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the reserves in `defaults` fit within this machine's totals.
+/// Disk is skipped when the partition size is unknown (0).
+pub fn validate_reserves(defaults: &PolicyDefaults, totals: &SystemTotals) -> io::Result<()> {
+    let disk_total = if totals.total_disk_gb > 0.0 {
+        totals.total_disk_gb
+    } else {
+        f64::MAX
+    };
+
+    for (key, reserve, total) in [
+        ("cpu_reserve", defaults.cpu_reserve, totals.total_cpu as f64),
+        ("mem_reserve", defaults.mem_reserve, totals.total_mem_gb),
+        ("disk_reserve", defaults.disk_reserve, disk_total),
+    ] {
+        if f64::from(reserve) > total {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} = {} exceeds this machine's total of {}",
+                    key,
+                    reserve,
+                    crate::system::format_decimal(total)
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// How the resources available to one user are derived: totals minus reserves
+/// minus what everyone else holds, capped by the dynamic fair share ceiling when
+/// it is enabled. Itemized for `request --explain`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Availability {
+    /// Machine totals (cpu, mem_gb, disk_gb)
+    pub totals: (f64, f64, f64),
+    /// System reserves (cpu, mem_gb, disk_gb)
+    pub reserves: (f64, f64, f64),
+    /// (cpu, mem_gb) set aside by scheduled holds active now
+    pub holds: (f64, f64),
+    /// (uid, (cpu, mem_gb, disk_gb)) held by every other user
+    pub other_users: Vec<(String, (f64, f64, f64))>,
+    /// Per-user fair share ceiling (cpu, mem_gb, disk_gb), None when disabled
+    pub ceiling: Option<(f64, f64, f64)>,
+    /// How `available` turns the remainder into whole CPUs and GB
+    pub rounding: Rounding,
+    /// Largest memory (GB) per requested CPU, None for no ratio check
+    pub max_mem_per_cpu_gb: Option<u32>,
+}
+
+/// Why `Availability::check` refused a request
+#[derive(Clone, Debug, PartialEq)]
+pub enum Refusal {
+    /// Memory per CPU is above `max_mem_per_cpu_gb`; carries the explanation
+    MemPerCpu(String),
+    /// Above the dynamic fair share ceiling
+    FairShare,
+    /// More than is left after reserves, holds and other users
+    Insufficient,
+}
+
+/// What `requesting_uid` can hold given the machine `totals`, every user's
+/// current allocation and the policy `limits`. Pure: the caller gathers the inputs.
+pub fn compute_availability(
+    totals: &SystemTotals,
+    users: &[UserAlloc],
+    limits: &AllocationLimits,
+    requesting_uid: Option<&str>,
+) -> Availability {
+    let (cpu_reserve, mem_reserve, disk_reserve) = limits.reserves;
+    let (min_free_cpu_pct, min_free_mem_pct) = limits.min_free_pct;
+
+    Availability {
+        totals: (
+            totals.total_cpu as f64,
+            totals.total_mem_gb,
+            totals.total_disk_gb,
+        ),
+        // Reserved capacity is free capacity, so the percentage floor only
+        // matters where it is larger than the absolute reserve
+        reserves: (
+            free_floor(cpu_reserve, totals.total_cpu as f64, min_free_cpu_pct),
+            free_floor(mem_reserve, totals.total_mem_gb, min_free_mem_pct),
+            disk_reserve as f64,
+        ),
+        holds: (limits.holds.0 as f64, limits.holds.1 as f64),
+        // Excluding the requesting user means we check whether the NET
+        // INCREASE fits, not the entire new request
+        other_users: held_by_other_users(
+            users,
+            &limits.login_uids,
+            limits.defaults,
+            requesting_uid,
+        ),
+        ceiling: limits.fair_share_users.map(|active_users| {
+            (
+                fair_share_ceiling(totals.total_cpu as f64, active_users),
+                fair_share_ceiling(totals.total_mem_gb, active_users),
+                fair_share_ceiling(totals.total_disk_gb, active_users),
+            )
+        }),
+        rounding: limits.rounding,
+        max_mem_per_cpu_gb: limits.max_mem_per_cpu_gb,
+    }
+}
+
+impl Availability {
+    /// (cpu, mem_gb, disk_gb) held by everyone but the requesting user, each login
+    /// user counting as at least the default
+    pub fn held(&self) -> (f64, f64, f64) {
+        total_held(&self.other_users)
+    }
+
+    /// Totals minus reserves, active holds and everyone else's allocations, before
+    /// any fair share cap
+    pub fn before_ceiling(&self) -> (f64, f64, f64) {
+        let (used_cpu, used_mem, used_disk) = self.held();
+        (
+            self.totals.0 - self.reserves.0 - self.holds.0 - used_cpu,
+            self.totals.1 - self.reserves.1 - self.holds.1 - used_mem,
+            self.totals.2 - self.reserves.2 - used_disk,
+        )
+    }
+
+    /// Whole CPUs and GB the user can hold: `before_ceiling` capped by the fair
+    /// share ceiling, rounded per the policy and never negative
+    pub fn available(&self) -> (u32, u32, u32) {
+        let (mut cpu, mut mem, mut disk) = self.before_ceiling();
+
+        // With dynamic fair share, never hand out more than the per-user ceiling
+        if let Some((cpu_ceiling, mem_ceiling, disk_ceiling)) = self.ceiling {
+            cpu = cpu.min(cpu_ceiling);
+            mem = mem.min(mem_ceiling);
+            disk = disk.min(disk_ceiling);
+        }
+
+        let whole = |value: f64| self.rounding.whole(value);
+        (whole(cpu), whole(mem), whole(disk))
+    }
+
+    /// Whether a request for `cpu` CPUs, `mem_gb` and `disk_gb` fits
+    pub fn check(&self, cpu: u32, mem_gb: f64, disk_gb: f64) -> Result<(), Refusal> {
+        if let Some(limit) = self.max_mem_per_cpu_gb {
+            if let Some(msg) = mem_per_cpu_violation(cpu, mem_gb, limit) {
+                return Err(Refusal::MemPerCpu(msg));
+            }
+        }
+
+        // Whatever `available` offers after rounding must also pass here
+        let offered = |value: f64| value.max(self.rounding.whole(value) as f64);
+        let within = |limits: (f64, f64, f64)| {
+            cpu as f64 <= offered(limits.0)
+                && mem_gb <= offered(limits.1)
+                && disk_gb <= offered(limits.2)
+        };
+
+        // With dynamic fair share, cap each user at total / active users
+        if self.ceiling.is_some_and(|ceiling| !within(ceiling)) {
+            return Err(Refusal::FairShare);
+        }
+        if !within(self.before_ceiling()) {
+            return Err(Refusal::Insufficient);
+        }
+
+        Ok(())
+    }
+}
+
+/// Message explaining why `req_mem_gb` for `req_cpu` CPUs breaks the policy's
+/// `max_mem_per_cpu_gb` ratio, or None if it is within the limit
+pub fn mem_per_cpu_violation(req_cpu: u32, req_mem_gb: f64, limit_gb: u32) -> Option<String> {
+    if req_mem_gb <= req_cpu as f64 * limit_gb as f64 {
+        return None;
+    }
+
+    Some(format!(
+        "requested {}G for {} CPU(s) exceeds the {}G-per-CPU limit",
+        req_mem_gb, req_cpu, limit_gb
+    ))
+}
+
+/// Per-user ceiling under dynamic fair share: total / max(active_users, 1)
+pub fn fair_share_ceiling(total: f64, active_users: usize) -> f64 {
+    total / active_users.max(1) as f64
+}
+
+/// Capacity that must stay unallocated: the absolute `reserve` or `min_free_pct`
+/// percent of `total`, whichever is larger
+fn free_floor(reserve: u32, total: f64, min_free_pct: u32) -> f64 {
+    (reserve as f64).max(total * min_free_pct as f64 / 100.0)
+}
+
+/// The (cpu, mem_gb, disk_gb) held by each user except `requesting_user_uid`, keyed by UID.
+/// Each user with UID >= 1000 counts as holding at least `default_alloc`, whether or
+/// not their slice is live, so availability doesn't depend on who has logged in.
+fn held_by_other_users(
+    allocations: &[UserAlloc],
+    login_uids: &[u32],
+    default_alloc: (u32, u32, u32),
+    requesting_user_uid: Option<&str>,
+) -> Vec<(String, (f64, f64, f64))> {
+    let (default_cpu, default_mem, default_disk) = (
+        default_alloc.0 as f64,
+        default_alloc.1 as f64,
+        default_alloc.2 as f64,
+    );
+
+    let mut held = vec![];
+    let mut counted: Vec<u32> = vec![];
+
+    for alloc in allocations {
+        if Some(alloc.uid.as_str()) == requesting_user_uid {
+            continue;
+        }
+
+        let mut cpu = alloc.cpu_quota / 100.0;
+        let mut mem = alloc.mem_bytes as f64 / 1_000_000_000.0;
+        let mut disk = alloc.disk_bytes as f64 / 1_000_000_000.0;
+
+        if let Ok(uid) = alloc.uid.parse::<u32>() {
+            if uid >= 1000 {
+                cpu = cpu.max(default_cpu);
+                mem = mem.max(default_mem);
+                disk = disk.max(default_disk);
+            }
+            counted.push(uid);
+        }
+
+        held.push((alloc.uid.clone(), (cpu, mem, disk)));
+    }
+
+    // Login users without a live slice still hold the default
+    for uid in login_uids {
+        if *uid < 1000
+            || counted.contains(uid)
+            || Some(uid.to_string().as_str()) == requesting_user_uid
+        {
+            continue;
+        }
+        counted.push(*uid);
+
+        held.push((uid.to_string(), (default_cpu, default_mem, default_disk)));
+    }
+
+    held
+}
+
+/// Sum of the per-user holdings from `held_by_other_users`
+fn total_held(held: &[(String, (f64, f64, f64))]) -> (f64, f64, f64) {
+    held.iter()
+        .fold((0.0, 0.0, 0.0), |sum, (_, (cpu, mem, disk))| {
+            (sum.0 + cpu, sum.1 + mem, sum.2 + disk)
+        })
+}
+
+/// Merge `overlay` into `base`. Nested tables merge key by key; any other
+/// value in `overlay` replaces the one in `base`.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn read_table(path: &Path) -> io::Result<toml::Table> {
+    let contents = fs::read_to_string(path)?;
+    toml::from_str::<toml::Table>(&contents).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Merge every `*.toml` file in `dir` into `table` in lexical order.
+/// Returns how many files were merged; a missing directory has none.
+fn merge_dropins(table: &mut toml::Table, dir: &Path) -> io::Result<usize> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    files.sort();
+
+    for file in &files {
+        merge_tables(table, read_table(file)?);
+    }
+
+    Ok(files.len())
+}
+
+/// Read every `*.toml` file in `dir` in lexical order and merge them, later files
+/// overriding earlier keys (systemd drop-in semantics). The merged result must be
+/// a complete, valid policy.
+pub fn load_policy_dir(dir: &Path) -> io::Result<PolicyConfig> {
+    let mut merged = toml::Table::new();
+    if merge_dropins(&mut merged, dir)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No policy files in {}", dir.display()),
+        ));
+    }
+
+    parse_policy(&merged.to_string())
+}
+
+/// Read a policy file and the drop-ins next to it (policy.toml + policy.d/*.toml),
+/// with drop-ins overriding the base file
+pub fn load_policy_from(path: &Path) -> io::Result<PolicyConfig> {
+    let dropin_dir = path.with_extension("d");
+    if !path.exists() {
+        return load_policy_dir(&dropin_dir);
+    }
+
+    let mut merged = read_table(path)?;
+    merge_dropins(&mut merged, &dropin_dir)?;
+    parse_policy(&merged.to_string())
+}
+
+/// Like `load_policy_from`, but a missing policy is Ok(None) rather than an error.
+/// A policy that exists but doesn't parse is still an error, naming the file and
+/// the line and column of the problem.
+pub fn read_policy(path: &Path) -> io::Result<Option<PolicyConfig>> {
+    match load_policy_from(path) {
+        Ok(policy) => Ok(Some(policy)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read /etc/fairshare/policy.toml with the overrides from its policy.d/
+/// Ok(None) if no policy exists; a policy that can't be parsed is an error so
+/// its reserves are never silently read as 0
+pub fn load_policy() -> io::Result<Option<PolicyConfig>> {
+    read_policy(Path::new(POLICY_PATH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy_written_by_admin_setup() {
+        // Same layout as the file written by admin_setup_defaults
+        let contents = "[defaults]\ncpu = 2\nmem = 4\ndisk = 10\ncpu_reserve = 1\nmem_reserve = 2\ndisk_reserve = 5\ndisk_partition = \"/var\"\n\n[max_caps]\ncpu = 20\nmem = 4\ndisk = 10\n";
+
+        let policy = parse_policy(contents).unwrap();
+        assert_eq!(policy.defaults.cpu, 2);
+        assert_eq!(policy.defaults.mem, 4);
+        assert_eq!(policy.defaults.disk, 10);
+        assert_eq!(policy.defaults.cpu_reserve, 1);
+        assert_eq!(policy.defaults.mem_reserve, 2);
+        assert_eq!(policy.defaults.disk_reserve, 5);
+        assert_eq!(policy.defaults.disk_partition.as_deref(), Some("/var"));
+        assert_eq!(
+            policy.max_caps,
+            Some(MaxCaps {
+                cpu: 20,
+                mem: 4,
+                disk: 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_policy_optional_fields_default() {
+        // Reserves, partition and max_caps are optional
+        let policy = parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n").unwrap();
+        assert_eq!(policy.defaults.cpu_reserve, 0);
+        assert_eq!(policy.defaults.mem_reserve, 0);
+        assert_eq!(policy.defaults.disk_reserve, 0);
+        assert_eq!(policy.defaults.disk_partition, None);
+        assert!(!policy.defaults.dynamic_fair_share);
+        assert!(!policy.defaults.allow_delegation);
+        assert_eq!(policy.defaults.max_mem_per_cpu_gb, None);
+        assert_eq!(policy.defaults.systemctl_timeout_secs, None);
+        assert_eq!(policy.max_caps, None);
+        assert!(policy.holds.is_empty());
+    }
+
+    #[test]
+    fn test_parse_policy_holds() {
+        let policy = parse_policy(
+            "[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n\n\
+             [[holds]]\ncpu = 16\nmem = 64\nfrom = \"02:00\"\nuntil = \"06:00\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            policy.holds,
+            vec![ScheduledHold {
+                cpu: 16,
+                mem: 64,
+                from: "02:00".to_string(),
+                until: "06:00".to_string(),
+            }]
+        );
+
+        let invalid = "[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n\n\
+                       [[holds]]\ncpu = 1\nmem = 1\nfrom = \"2am\"\nuntil = \"06:00\"\n";
+        assert!(parse_policy(invalid).is_err());
+        let empty = "[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n\n\
+                     [[holds]]\ncpu = 1\nmem = 1\nfrom = \"06:00\"\nuntil = \"06:00\"\n";
+        assert!(parse_policy(empty).is_err());
+    }
+
+    #[test]
+    fn test_append_hold() {
+        let base = "[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n\n";
+        let once = append_hold(base, 16, 64, "02:00", "06:00").unwrap();
+        let twice = append_hold(&once, 4, 8, "22:00", "01:00").unwrap();
+
+        let policy = parse_policy(&twice).unwrap();
+        assert_eq!(policy.holds.len(), 2);
+        assert_eq!(policy.holds[1].from, "22:00");
+        assert_eq!(policy.defaults.cpu, 1);
+
+        assert!(append_hold(base, 1, 1, "05:00", "05:00").is_err());
+    }
+
+    #[test]
+    fn test_minutes_of_day() {
+        assert_eq!(minutes_of_day("00:00"), Some(0));
+        assert_eq!(minutes_of_day("02:30"), Some(150));
+        assert_eq!(minutes_of_day("23:59"), Some(1439));
+        assert_eq!(minutes_of_day("24:00"), None);
+        assert_eq!(minutes_of_day("2:00"), None);
+        assert_eq!(minutes_of_day("02:60"), None);
+    }
+
+    #[test]
+    fn test_hold_window_same_day() {
+        let hold = ScheduledHold {
+            cpu: 16,
+            mem: 64,
+            from: "02:00".to_string(),
+            until: "06:00".to_string(),
+        };
+        assert!(!hold.is_active_at(119)); // 01:59
+        assert!(hold.is_active_at(120)); // 02:00
+        assert!(hold.is_active_at(359)); // 05:59
+        assert!(!hold.is_active_at(360)); // 06:00
+    }
+
+    #[test]
+    fn test_hold_window_crossing_midnight() {
+        let hold = ScheduledHold {
+            cpu: 16,
+            mem: 64,
+            from: "22:00".to_string(),
+            until: "02:00".to_string(),
+        };
+        assert!(hold.is_active_at(23 * 60));
+        assert!(hold.is_active_at(0));
+        assert!(hold.is_active_at(60));
+        assert!(!hold.is_active_at(120));
+        assert!(!hold.is_active_at(12 * 60));
+        assert!(!hold.is_active_at(21 * 60 + 59));
+    }
+
+    #[test]
+    fn test_parse_policy_max_mem_per_cpu() {
+        let policy =
+            parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\nmax_mem_per_cpu_gb = 16\n")
+                .unwrap();
+        assert_eq!(policy.defaults.max_mem_per_cpu_gb, Some(16));
+    }
+
+    #[test]
+    fn test_parse_policy_io_weight() {
+        let policy =
+            parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\nio_weight = 200\n").unwrap();
+        assert_eq!(policy.defaults.io_weight, Some(200));
+
+        let unset = parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n").unwrap();
+        assert_eq!(unset.defaults.io_weight, None);
+
+        for weight in [0, 10001] {
+            let contents = format!(
+                "[defaults]\ncpu = 1\nmem = 2\ndisk = 0\nio_weight = {}\n",
+                weight
+            );
+            assert!(parse_policy(&contents).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_policy_tasks_max() {
+        let policy =
+            parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\ntasks_max = 4096\n").unwrap();
+        assert_eq!(policy.defaults.tasks_max, Some(4096));
+
+        let unset = parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n").unwrap();
+        assert_eq!(unset.defaults.tasks_max, None);
+
+        assert!(parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\ntasks_max = 0\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_policy_min_free_pct() {
+        let policy = parse_policy(
+            "[defaults]\ncpu = 1\nmem = 2\ndisk = 0\nmin_free_cpu_pct = 10\nmin_free_mem_pct = 20\n",
+        )
+        .unwrap();
+        assert_eq!(
+            (
+                policy.defaults.min_free_cpu_pct,
+                policy.defaults.min_free_mem_pct
+            ),
+            (10, 20)
+        );
+
+        let unset = parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n").unwrap();
+        assert_eq!(unset.defaults.min_free_cpu_pct, 0);
+
+        assert!(
+            parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\nmin_free_mem_pct = 101\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_policy_rounding() {
+        let policy =
+            parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\nrounding = \"nearest\"\n")
+                .unwrap();
+        assert_eq!(policy.defaults.rounding, Rounding::Nearest);
+
+        let unset = parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n").unwrap();
+        assert_eq!(unset.defaults.rounding, Rounding::Floor);
+
+        assert!(
+            parse_policy("[defaults]\ncpu = 1\nmem = 2\ndisk = 0\nrounding = \"up\"\n").is_err()
+        );
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        assert_eq!(
+            (Rounding::Floor.whole(3.9), Rounding::Floor.whole(3.1)),
+            (3, 3)
+        );
+        assert_eq!(
+            (Rounding::Ceil.whole(3.9), Rounding::Ceil.whole(3.1)),
+            (4, 4)
+        );
+        assert_eq!(
+            (Rounding::Nearest.whole(3.9), Rounding::Nearest.whole(3.1)),
+            (4, 3)
+        );
+
+        // Oversubscribed or exhausted capacity is never rounded up to something
+        for mode in [Rounding::Floor, Rounding::Ceil, Rounding::Nearest] {
+            assert_eq!(mode.whole(-0.4), 0);
+            assert_eq!(mode.whole(0.0), 0);
+        }
+    }
+
+    #[test]
+    fn test_parse_policy_invalid() {
+        let result = parse_policy("not = [valid");
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Missing [defaults] table
+        assert!(parse_policy("[max_caps]\ncpu = 1\nmem = 1\n").is_err());
+    }
+
+    #[test]
+    fn test_load_policy_from_file() {
+        let path =
+            std::env::temp_dir().join(format!("fairshare-policy-test-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "[defaults]\ncpu = 3\nmem = 6\ndisk = 0\nmem_reserve = 4\n",
+        )
+        .unwrap();
+
+        let policy = load_policy_from(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(policy.defaults.cpu, 3);
+        assert_eq!(policy.defaults.mem, 6);
+        assert_eq!(policy.defaults.mem_reserve, 4);
+    }
+
+    #[test]
+    fn test_load_policy_from_missing_file() {
+        let result = load_policy_from(Path::new("/nonexistent/fairshare/policy.toml"));
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_read_policy_absent_valid_and_broken() {
+        // Absent: no policy at all, which means no reserves
+        let missing = read_policy(Path::new("/nonexistent/fairshare/policy.toml"));
+        assert!(missing.unwrap().is_none());
+
+        let dir = dropin_test_dir("read-policy");
+        let path = dir.join("policy.toml");
+
+        fs::write(
+            &path,
+            "[defaults]\ncpu = 1\nmem = 2\ndisk = 0\ncpu_reserve = 4\n",
+        )
+        .unwrap();
+        let valid = read_policy(&path);
+
+        // A typo (unterminated string) on line 3
+        fs::write(&path, "[defaults]\ncpu = 1\nmem_reserve = \"4\ndisk = 0\n").unwrap();
+        let broken = read_policy(&path);
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(valid.unwrap().unwrap().defaults.cpu_reserve, 4);
+
+        let err = broken.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let message = err.to_string();
+        assert!(message.contains(&path.display().to_string()), "{}", message);
+        assert!(message.contains("line 3"), "{}", message);
+    }
+
+    fn dropin_test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "fairshare-policy-d-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_load_policy_dir_later_file_overrides() {
+        let dir = dropin_test_dir("override");
+        fs::write(
+            dir.join("10-base.toml"),
+            "[defaults]\ncpu = 1\nmem = 2\ndisk = 0\ncpu_reserve = 2\n\n[max_caps]\ncpu = 8\nmem = 16\n",
+        )
+        .unwrap();
+        fs::write(dir.join("50-site.toml"), "[max_caps]\ncpu = 32\n").unwrap();
+        // Non-toml files are ignored
+        fs::write(dir.join("99-notes.txt"), "[max_caps]\ncpu = 1\n").unwrap();
+
+        let policy = load_policy_dir(&dir);
+        fs::remove_dir_all(&dir).ok();
+        let policy = policy.unwrap();
+
+        let caps = policy.max_caps.unwrap();
+        assert_eq!(caps.cpu, 32);
+        // Keys not set in the override are kept from the base
+        assert_eq!(caps.mem, 16);
+        assert_eq!(policy.defaults.cpu, 1);
+        assert_eq!(policy.defaults.cpu_reserve, 2);
+    }
+
+    #[test]
+    fn test_load_policy_from_applies_dropins_over_base_file() {
+        let dir = dropin_test_dir("layered");
+        let base = dir.join("policy.toml");
+        let dropins = dir.join("policy.d");
+        fs::create_dir_all(&dropins).unwrap();
+        fs::write(&base, "[defaults]\ncpu = 1\nmem = 2\ndisk = 0\n").unwrap();
+        fs::write(dropins.join("override.toml"), "[defaults]\nmem = 4\n").unwrap();
+
+        let policy = load_policy_from(&base);
+        fs::remove_dir_all(&dir).ok();
+        let policy = policy.unwrap();
+
+        assert_eq!(policy.defaults.cpu, 1);
+        assert_eq!(policy.defaults.mem, 4);
+    }
+
+    #[test]
+    fn test_load_policy_dir_validates_merged_result() {
+        // Drop-ins that never provide [defaults] don't form a valid policy
+        let dir = dropin_test_dir("invalid");
+        fs::write(dir.join("caps.toml"), "[max_caps]\ncpu = 4\nmem = 4\n").unwrap();
+
+        let result = load_policy_dir(&dir);
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_policy_consistency_defaults_within_caps() {
+        let policy = parse_policy(
+            "[defaults]\ncpu = 4\nmem = 8\ndisk = 10\n\n[max_caps]\ncpu = 40\nmem = 8\n",
+        )
+        .unwrap();
+        assert!(validate_policy_consistency(&policy).is_ok());
+    }
+
+    #[test]
+    fn test_policy_consistency_default_exceeds_cap() {
+        let err = parse_policy(
+            "[defaults]\ncpu = 4\nmem = 16\ndisk = 0\n\n[max_caps]\ncpu = 40\nmem = 8\n",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "[defaults] mem = 16 exceeds [max_caps] mem = 8"
+        );
+
+        let err = parse_policy(
+            "[defaults]\ncpu = 1\nmem = 1\ndisk = 20\n\n[max_caps]\ncpu = 10\nmem = 1\ndisk = 10\n",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("disk = 20"));
+    }
+
+    #[test]
+    fn test_validate_reserves_against_totals() {
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 0.0,
+        };
+        let mut defaults = parse_policy("[defaults]\ncpu = 1\nmem = 1\ndisk = 0\ncpu_reserve = 16\nmem_reserve = 32\ndisk_reserve = 500\n")
+            .unwrap()
+            .defaults;
+        // Unknown disk size skips the disk check
+        assert!(validate_reserves(&defaults, &totals).is_ok());
+
+        defaults.cpu_reserve = 17;
+        let err = validate_reserves(&defaults, &totals).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cpu_reserve = 17 exceeds this machine's total of 16.00"
+        );
+    }
+
+    #[test]
+    fn test_load_policy_dir_missing_or_empty() {
+        let result = load_policy_dir(Path::new("/nonexistent/fairshare/policy.d"));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    fn alloc(uid: &str, cpu_quota: f64, mem_gb: u64, disk_gb: u64) -> UserAlloc {
+        UserAlloc {
+            uid: uid.to_string(),
+            cpu_quota,
+            mem_bytes: mem_gb * 1_000_000_000,
+            disk_bytes: disk_gb * 1_000_000_000,
+        }
+    }
+
+    /// Whether (cpu, mem_gb, disk_gb) fits for `requesting_uid`
+    fn fits(
+        totals: &SystemTotals,
+        users: &[UserAlloc],
+        (cpu, mem_gb, disk_gb): (u32, f64, f64),
+        requesting_uid: Option<&str>,
+        limits: &AllocationLimits,
+    ) -> bool {
+        compute_availability(totals, users, limits, requesting_uid)
+            .check(cpu, mem_gb, disk_gb)
+            .is_ok()
+    }
+
+    #[test]
+    fn test_check_refusal_reasons() {
+        let totals = SystemTotals {
+            total_mem_gb: 64.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let limits = AllocationLimits {
+            fair_share_users: Some(2),
+            max_mem_per_cpu_gb: Some(8),
+            ..AllocationLimits::default()
+        };
+        let users = vec![alloc("1000", 800.0, 8, 0)];
+        let availability = compute_availability(&totals, &users, &limits, Some("1001"));
+
+        assert_eq!(availability.check(4, 16.0, 0.0), Ok(()));
+        assert!(matches!(
+            availability.check(1, 16.0, 0.0),
+            Err(Refusal::MemPerCpu(_))
+        ));
+        // Two active users: nobody may hold more than 8 CPUs
+        assert_eq!(availability.check(9, 16.0, 0.0), Err(Refusal::FairShare));
+        // Under the ceiling, but 1000 now holds 12 of the 16 CPUs
+        let crowded = vec![alloc("1000", 1200.0, 8, 0)];
+        let availability = compute_availability(&totals, &crowded, &limits, Some("1001"));
+        assert_eq!(availability.check(5, 16.0, 0.0), Err(Refusal::Insufficient));
+    }
+
+    #[test]
+    fn test_check_sufficient_resources() {
+        let totals = SystemTotals {
+            total_mem_gb: 16.0,
+            total_cpu: 8,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![UserAlloc {
+            uid: "1000".to_string(),
+            cpu_quota: 200.0,         // 2 CPUs
+            mem_bytes: 4_000_000_000, // 4 GB
+            disk_bytes: 0,
+        }];
+
+        // Request 2 CPUs and 4 GB - should be allowed
+        assert!(fits(
+            &totals,
+            &allocations,
+            (2, 4.0, 0.0),
+            None,
+            &AllocationLimits::default()
+        ));
+    }
+
+    #[test]
+    fn test_check_insufficient_cpu() {
+        let totals = SystemTotals {
+            total_mem_gb: 16.0,
+            total_cpu: 8,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![UserAlloc {
+            uid: "1000".to_string(),
+            cpu_quota: 600.0,         // 6 CPUs
+            mem_bytes: 4_000_000_000, // 4 GB
+            disk_bytes: 0,
+        }];
+
+        // Request 4 CPUs when only 2 are available - should fail
+        assert!(!fits(
+            &totals,
+            &allocations,
+            (4, 4.0, 0.0),
+            None,
+            &AllocationLimits::default()
+        ));
+    }
+
+    #[test]
+    fn test_check_insufficient_memory() {
+        let totals = SystemTotals {
+            total_mem_gb: 16.0,
+            total_cpu: 8,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![UserAlloc {
+            uid: "1000".to_string(),
+            cpu_quota: 200.0,          // 2 CPUs
+            mem_bytes: 12_000_000_000, // 12 GB
+            disk_bytes: 0,
+        }];
+
+        // Request 8 GB when only 4 GB available - should fail
+        assert!(!fits(
+            &totals,
+            &allocations,
+            (2, 8.0, 0.0),
+            None,
+            &AllocationLimits::default()
+        ));
+    }
+
+    #[test]
+    fn test_check_multiple_users() {
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![
+            UserAlloc {
+                uid: "1000".to_string(),
+                cpu_quota: 400.0,         // 4 CPUs
+                mem_bytes: 8_000_000_000, // 8 GB
+                disk_bytes: 0,
+            },
+            UserAlloc {
+                uid: "1001".to_string(),
+                cpu_quota: 200.0,         // 2 CPUs
+                mem_bytes: 4_000_000_000, // 4 GB
+                disk_bytes: 0,
+            },
+        ];
+
+        // 6 CPUs used, 12 GB used
+        // Request 5 CPUs and 10 GB - should be allowed (10 available, 20 available)
+        assert!(fits(
+            &totals,
+            &allocations,
+            (5, 10.0, 0.0),
+            None,
+            &AllocationLimits::default()
+        ));
+
+        // Request 12 CPUs - should fail (only 10 available)
+        assert!(!fits(
+            &totals,
+            &allocations,
+            (12, 8.0, 0.0),
+            None,
+            &AllocationLimits::default()
+        ));
+    }
+
+    #[test]
+    fn test_check_exact_available() {
+        let (cpu_reserve, mem_reserve, disk_reserve) = (2, 4, 4);
+        let limits = AllocationLimits {
+            reserves: (cpu_reserve, mem_reserve, disk_reserve),
+            ..AllocationLimits::default()
+        };
+
+        let totals = SystemTotals {
+            total_mem_gb: 16.0,
+            total_cpu: 8,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![UserAlloc {
+            uid: "1000".to_string(),
+            cpu_quota: 400.0,         // 4 CPUs
+            mem_bytes: 8_000_000_000, // 8 GB
+            disk_bytes: 0,
+        }];
+
+        // Calculate actual available resources considering reserves
+        // Available = Total - Used - Reserve
+        // Available CPU = 8 - 4 - cpu_reserve
+        // Available MEM = 16 - 8 - mem_reserve
+        let available_cpu = (8u32 - 4u32).saturating_sub(cpu_reserve);
+        let available_mem = (16u32 - 8u32).saturating_sub(mem_reserve);
+        let available_disk = 100.0 - (disk_reserve as f64);
+
+        // Request exactly what's available (should succeed)
+        assert!(fits(
+            &totals,
+            &allocations,
+            (available_cpu, available_mem as f64, available_disk),
+            None,
+            &limits
+        ));
+
+        // Request more than available (should fail)
+        assert!(!fits(
+            &totals,
+            &allocations,
+            (available_cpu + 1, available_mem as f64, available_disk),
+            None,
+            &limits
+        ));
+    }
+
+    #[test]
+    fn test_check_user_modifying_own_allocation() {
+        let (cpu_reserve, mem_reserve) = (2, 4);
+        let limits = AllocationLimits {
+            reserves: (cpu_reserve, mem_reserve, 0),
+            ..AllocationLimits::default()
+        };
+
+        // Use larger system to accommodate reserves and test scenarios
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![
+            UserAlloc {
+                uid: "1000".to_string(),
+                cpu_quota: 400.0,          // 4 CPUs
+                mem_bytes: 10_000_000_000, // 10 GB
+                disk_bytes: 0,
+            },
+            UserAlloc {
+                uid: "1001".to_string(),
+                cpu_quota: 200.0,         // 2 CPUs
+                mem_bytes: 5_000_000_000, // 5 GB
+                disk_bytes: 0,
+            },
+        ];
+
+        // Total used: 6 CPUs, 15 GB
+        // User 1000 requests 5 CPUs and 11 GB (increase of 1 CPU and 1 GB)
+        // Delta: adjusted_used = (6-4, 15-10) = (2 CPUs, 5 GB)
+        // Available = (16 - 2 - cpu_reserve, 32 - 5 - mem_reserve)
+        // With reserves (2, 4): Available = (12, 23)
+        // Request: 5 CPUs, 11 GB - should succeed since 5 <= 12 and 11 <= 23
+        assert!(fits(
+            &totals,
+            &allocations,
+            (5, 11.0, 0.0),
+            Some("1000"),
+            &limits
+        ));
+
+        // User 1001 trying to request 1 CPU and 3 GB (decrease from 2 CPUs, 5 GB)
+        // Should definitely succeed as this is a decrease
+        assert!(fits(
+            &totals,
+            &allocations,
+            (1, 3.0, 0.0),
+            Some("1001"),
+            &limits
+        ));
+
+        // Calculate what's actually available for a new user
+        // Used: 6 CPUs, 15 GB
+        // Available = (16 - 6 - cpu_reserve, 32 - 15 - mem_reserve)
+        // With reserves (2, 4): Available = (8, 13)
+        let avail_cpu_for_new = (16u32 - 6u32).saturating_sub(cpu_reserve);
+        let avail_mem_for_new = (32u32 - 15u32).saturating_sub(mem_reserve);
+
+        // New user 1002 requesting within available (should succeed)
+        assert!(fits(
+            &totals,
+            &allocations,
+            (
+                avail_cpu_for_new.min(1),
+                avail_mem_for_new.min(1) as f64,
+                0.0
+            ),
+            Some("1002"),
+            &limits
+        ));
+
+        // User 1000 requesting way too much even with delta (should fail)
+        // Current: 4 CPUs. Request: 20 CPUs. Net: +16 CPUs.
+        // Available with delta = (16 - 2 - cpu_reserve) = 12 or less
+        // 20 > 12, so should fail
+        assert!(!fits(
+            &totals,
+            &allocations,
+            (20, 15.0, 0.0),
+            Some("1000"),
+            &limits
+        ));
+    }
+
+    #[test]
+    fn test_held_by_other_users_same_with_or_without_live_slice() {
+        let defaults = (2, 4, 10);
+        let login_uids = vec![1000, 1001];
+
+        // User 1001 has never logged in: no slice exists
+        let without_slice = vec![UserAlloc {
+            uid: "1000".to_string(),
+            cpu_quota: 200.0,
+            mem_bytes: 4_000_000_000,
+            disk_bytes: 10_000_000_000,
+        }];
+
+        // User 1001 has a live slice carrying the default from 00-defaults.conf
+        let mut with_slice = vec![UserAlloc {
+            uid: "1000".to_string(),
+            cpu_quota: 200.0,
+            mem_bytes: 4_000_000_000,
+            disk_bytes: 10_000_000_000,
+        }];
+        with_slice.push(UserAlloc {
+            uid: "1001".to_string(),
+            cpu_quota: 200.0,
+            mem_bytes: 4_000_000_000,
+            disk_bytes: 10_000_000_000,
+        });
+
+        let a = total_held(&held_by_other_users(
+            &without_slice,
+            &login_uids,
+            defaults,
+            Some("1002"),
+        ));
+        let b = total_held(&held_by_other_users(
+            &with_slice,
+            &login_uids,
+            defaults,
+            Some("1002"),
+        ));
+        assert_eq!(a, (4.0, 8.0, 20.0));
+        assert_eq!(a, b);
+
+        // Same holds when 1001 is the one asking: their default is never counted
+        let a = total_held(&held_by_other_users(
+            &without_slice,
+            &login_uids,
+            defaults,
+            Some("1001"),
+        ));
+        let b = total_held(&held_by_other_users(
+            &with_slice,
+            &login_uids,
+            defaults,
+            Some("1001"),
+        ));
+        assert_eq!(a, (2.0, 4.0, 10.0));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_availability_components_sum_to_available() {
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![alloc("1000", 400.0, 8, 0), alloc("1001", 150.0, 3, 10)];
+        let limits = AllocationLimits {
+            reserves: (2, 4, 5),
+            defaults: (1, 2, 0),
+            fair_share_users: None,
+            login_uids: vec![1000, 1001, 1002, 1003],
+            max_mem_per_cpu_gb: None,
+            holds: (0, 0),
+            min_free_pct: (0, 0),
+            rounding: Rounding::Floor,
+        };
+
+        let availability = compute_availability(&totals, &allocations, &limits, Some("1002"));
+
+        // 1000 and 1001 from their slices, 1003 at the default; 1002 is excluded
+        let uids: Vec<&str> = availability
+            .other_users
+            .iter()
+            .map(|(u, _)| u.as_str())
+            .collect();
+        assert_eq!(uids, vec!["1000", "1001", "1003"]);
+
+        let (held_cpu, held_mem, held_disk) = total_held(&availability.other_users);
+        assert_eq!((held_cpu, held_mem, held_disk), (6.5, 13.0, 10.0));
+        assert_eq!(
+            availability.before_ceiling(),
+            (
+                16.0 - 2.0 - held_cpu,
+                32.0 - 4.0 - held_mem,
+                100.0 - 5.0 - held_disk
+            )
+        );
+        // 7.5 CPUs round down to 7
+        assert_eq!(availability.available(), (7, 15, 85));
+    }
+
+    #[test]
+    fn test_availability_applies_fair_share_ceiling() {
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let limits = AllocationLimits {
+            fair_share_users: Some(4),
+            ..AllocationLimits::default()
+        };
+
+        let availability = compute_availability(&totals, &[], &limits, Some("1000"));
+
+        assert_eq!(availability.before_ceiling(), (16.0, 32.0, 100.0));
+        assert_eq!(availability.ceiling, Some((4.0, 8.0, 25.0)));
+        assert_eq!(availability.available(), (4, 8, 25));
+    }
+
+    #[test]
+    fn test_held_by_other_users_live_slice_above_default() {
+        let allocations = vec![UserAlloc {
+            uid: "1000".to_string(),
+            cpu_quota: 600.0,
+            mem_bytes: 1_000_000_000,
+            disk_bytes: 0,
+        }];
+
+        // Larger live values win, smaller ones are raised to the default
+        let used = total_held(&held_by_other_users(
+            &allocations,
+            &[1000],
+            (2, 4, 10),
+            None,
+        ));
+        assert_eq!(used, (6.0, 4.0, 10.0));
+    }
+
+    #[test]
+    fn test_held_by_other_users_without_policy() {
+        // No policy defaults: only live slices count, as before
+        let allocations = vec![UserAlloc {
+            uid: "1000".to_string(),
+            cpu_quota: 100.0,
+            mem_bytes: 2_000_000_000,
+            disk_bytes: 0,
+        }];
+
+        let used = total_held(&held_by_other_users(
+            &allocations,
+            &[1000, 1001, 1002],
+            (0, 0, 0),
+            None,
+        ));
+        assert_eq!(used, (1.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn test_fair_share_ceiling_one_user() {
+        // A single active user may use everything
+        assert_eq!(fair_share_ceiling(16.0, 1), 16.0);
+        assert_eq!(fair_share_ceiling(64.0, 1), 64.0);
+    }
+
+    #[test]
+    fn test_fair_share_ceiling_four_users() {
+        assert_eq!(fair_share_ceiling(16.0, 4), 4.0);
+        assert_eq!(fair_share_ceiling(64.0, 4), 16.0);
+    }
+
+    #[test]
+    fn test_fair_share_ceiling_ten_users() {
+        assert_eq!(fair_share_ceiling(16.0, 10), 1.6);
+        assert_eq!(fair_share_ceiling(64.0, 10), 6.4);
+    }
+
+    #[test]
+    fn test_fair_share_ceiling_no_active_users() {
+        // Nobody logged in (e.g. admin set-user) is treated as one user
+        assert_eq!(fair_share_ceiling(16.0, 0), 16.0);
+    }
+
+    #[test]
+    fn test_check_subtracts_active_holds() {
+        let totals = SystemTotals {
+            total_mem_gb: 128.0,
+            total_cpu: 32,
+            total_disk_gb: 100.0,
+        };
+        let limits = AllocationLimits {
+            holds: (16, 64),
+            ..AllocationLimits::default()
+        };
+
+        assert!(fits(&totals, &[], (16, 64.0, 0.0), None, &limits));
+        assert!(!fits(&totals, &[], (17, 64.0, 0.0), None, &limits));
+        assert_eq!(
+            compute_availability(&totals, &[], &limits, None).available(),
+            (16, 64, 100)
+        );
+    }
+
+    #[test]
+    fn test_availability_rounding_modes() {
+        // 3.9 CPUs and 3.9G free
+        let almost_four = SystemTotals {
+            total_mem_gb: 15.9,
+            total_cpu: 8,
+            total_disk_gb: 0.0,
+        };
+        // 3.1 CPUs and 3.1G free
+        let just_over_three = SystemTotals {
+            total_mem_gb: 15.1,
+            total_cpu: 8,
+            total_disk_gb: 0.0,
+        };
+
+        for (rounding, (high, low)) in [
+            (Rounding::Floor, (3, 3)),
+            (Rounding::Ceil, (4, 4)),
+            (Rounding::Nearest, (4, 3)),
+        ] {
+            let limits = AllocationLimits {
+                rounding,
+                ..AllocationLimits::default()
+            };
+            let available = |totals: &SystemTotals, cpu_quota: f64| {
+                let allocations = vec![alloc("1000", cpu_quota, 12, 0)];
+                compute_availability(totals, &allocations, &limits, None).available()
+            };
+
+            assert_eq!(
+                available(&almost_four, 410.0),
+                (high, high, 0),
+                "{:?}",
+                rounding
+            );
+            assert_eq!(
+                available(&just_over_three, 490.0),
+                (low, low, 0),
+                "{:?}",
+                rounding
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_accepts_rounded_up_availability() {
+        let totals = SystemTotals {
+            total_mem_gb: 15.9,
+            total_cpu: 8,
+            total_disk_gb: 0.0,
+        };
+        let allocations = vec![alloc("1000", 410.0, 12, 0)];
+
+        let fits = |rounding| {
+            let limits = AllocationLimits {
+                rounding,
+                ..AllocationLimits::default()
+            };
+            fits(&totals, &allocations, (4, 4.0, 0.0), None, &limits)
+        };
+
+        // `request --all` under ceil/nearest offers 4 of the 3.9 free, so 4 must pass
+        assert!(!fits(Rounding::Floor));
+        assert!(fits(Rounding::Ceil));
+        assert!(fits(Rounding::Nearest));
+    }
+
+    #[test]
+    fn test_check_min_free_pct_floor() {
+        let totals = SystemTotals {
+            total_mem_gb: 100.0,
+            total_cpu: 20,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![alloc("1000", 1000.0, 50, 0)];
+
+        // Reserves of 1 CPU/2G leave 9 CPUs/48G, but a 20% floor keeps 4 CPUs/20G free
+        let limits = AllocationLimits {
+            reserves: (1, 2, 0),
+            min_free_pct: (20, 20),
+            ..AllocationLimits::default()
+        };
+        assert!(!fits(&totals, &allocations, (9, 48.0, 0.0), None, &limits));
+        assert!(fits(&totals, &allocations, (6, 30.0, 0.0), None, &limits));
+        assert!(!fits(&totals, &allocations, (7, 30.0, 0.0), None, &limits));
+
+        // Vice versa: a 5% floor (1 CPU/5G) is below reserves of 4 CPUs/10G
+        let limits = AllocationLimits {
+            reserves: (4, 10, 0),
+            min_free_pct: (5, 5),
+            ..AllocationLimits::default()
+        };
+        assert!(fits(&totals, &allocations, (6, 40.0, 0.0), None, &limits));
+        assert!(!fits(&totals, &allocations, (7, 40.0, 0.0), None, &limits));
+        assert!(!fits(&totals, &allocations, (6, 41.0, 0.0), None, &limits));
+    }
+
+    #[test]
+    fn test_mem_per_cpu_violation_message() {
+        assert_eq!(
+            mem_per_cpu_violation(1, 200.0, 16),
+            Some("requested 200G for 1 CPU(s) exceeds the 16G-per-CPU limit".to_string())
+        );
+        assert_eq!(mem_per_cpu_violation(2, 32.0, 16), None);
+    }
+
+    #[test]
+    fn test_check_mem_per_cpu_ratio() {
+        let totals = SystemTotals {
+            total_mem_gb: 512.0,
+            total_cpu: 64,
+            total_disk_gb: 100.0,
+        };
+        let limits = AllocationLimits {
+            max_mem_per_cpu_gb: Some(16),
+            ..AllocationLimits::default()
+        };
+
+        // Below and at 16G per CPU
+        assert!(fits(&totals, &[], (2, 16.0, 0.0), None, &limits));
+        assert!(fits(&totals, &[], (2, 32.0, 0.0), None, &limits));
+        // Above, even though the machine has the memory free
+        assert!(!fits(&totals, &[], (2, 33.0, 0.0), None, &limits));
+        assert!(!fits(&totals, &[], (1, 200.0, 0.0), None, &limits));
+    }
+
+    #[test]
+    fn test_check_mem_per_cpu_unset_is_unlimited() {
+        let totals = SystemTotals {
+            total_mem_gb: 512.0,
+            total_cpu: 64,
+            total_disk_gb: 100.0,
+        };
+
+        assert!(fits(
+            &totals,
+            &[],
+            (1, 200.0, 0.0),
+            None,
+            &AllocationLimits::default()
+        ));
+    }
+
+    #[test]
+    fn test_check_insufficient_disk() {
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![UserAlloc {
+            uid: "1000".to_string(),
+            cpu_quota: 200.0,           // 2 CPUs
+            mem_bytes: 4_000_000_000,   // 4 GB
+            disk_bytes: 50_000_000_000, // 50 GB
+        }];
+
+        // Request 60 GB disk (Total 100 - Used 50 = 50 Available)
+        // 60 > 50 -> Should fail
+        assert!(!fits(
+            &totals,
+            &allocations,
+            (2, 4.0, 60.0),
+            None,
+            &AllocationLimits::default()
+        ));
+
+        // Request 40 GB disk -> Should succeed
+        assert!(fits(
+            &totals,
+            &allocations,
+            (2, 4.0, 40.0),
+            None,
+            &AllocationLimits::default()
+        ));
+    }
+}
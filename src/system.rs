@@ -1,34 +1,22 @@
 use colored::*;
-use comfy_table::{modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Cell, Color, Table};
-use serde::Deserialize;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::{ASCII_FULL, ASCII_NO_BORDERS, NOTHING, UTF8_FULL};
+use comfy_table::{Cell, Color, Table};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io;
-use std::process::Command;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
 use sysinfo::System;
 use users::{get_user_by_name, get_user_by_uid, uid_t};
 
-#[derive(Deserialize)]
-struct PolicyConfig {
-    defaults: PolicyDefaults,
-}
-
-#[derive(Deserialize)]
-struct PolicyDefaults {
-    #[allow(dead_code)]
-    cpu: u32,
-    #[allow(dead_code)]
-    mem: u32,
-    #[allow(dead_code)]
-    disk: u32,
-    #[serde(default)]
-    cpu_reserve: u32,
-    #[serde(default)]
-    mem_reserve: u32,
-    #[serde(default)]
-    disk_reserve: u32,
-    #[serde(default)]
-    disk_partition: Option<String>,
-}
+use crate::cli::{SetupProfile, StatusSort, TableStyle, MAX_CPU, MAX_MEM, MIN_CPU, MIN_MEM};
+use crate::policy::{
+    compute_availability, Availability, PolicyConfig, Refusal, Rounding, ScheduledHold,
+};
 
 pub struct SystemTotals {
     pub total_mem_gb: f64,
@@ -36,6 +24,7 @@ pub struct SystemTotals {
     pub total_disk_gb: f64,
 }
 
+#[derive(Clone)]
 pub struct UserAlloc {
     pub uid: String,
     pub cpu_quota: f64,
@@ -43,62 +32,136 @@ pub struct UserAlloc {
     pub disk_bytes: u64,
 }
 
-/// Read the system CPU reserve from policy.toml
-/// Returns 0 if the file doesn't exist or can't be read
-pub fn get_system_cpu_reserve() -> u32 {
-    let policy_path = "/etc/fairshare/policy.toml";
+/// Format a CPU or memory figure with two decimals. Rust's formatting never
+/// consults the locale, so this always uses `.` regardless of `LC_NUMERIC`.
+pub fn format_decimal(value: f64) -> String {
+    format!("{:.2}", value)
+}
+
+/// Whether `alloc` differs from the per-user policy default (cpu, mem, disk).
+/// Without a policy, any limit at all counts as a custom allocation.
+pub fn has_custom_allocation(alloc: Option<&UserAlloc>, defaults: Option<(u32, u32, u32)>) -> bool {
+    let alloc = match alloc {
+        Some(alloc) => alloc,
+        None => return false,
+    };
+
+    let (default_cpu, default_mem, default_disk) = defaults.unwrap_or((0, 0, 0));
 
-    match fs::read_to_string(policy_path) {
-        Ok(contents) => match toml::from_str::<PolicyConfig>(&contents) {
-            Ok(config) => config.defaults.cpu_reserve,
-            Err(_) => 0,
-        },
-        Err(_) => 0,
-    }
+    alloc.cpu_quota.round() as u64 != default_cpu as u64 * 100
+        || alloc.mem_bytes != default_mem as u64 * 1_000_000_000
+        || (alloc.disk_bytes > 0 && alloc.disk_bytes != default_disk as u64 * 1_000_000_000)
 }
 
-/// Read the system memory reserve from policy.toml
-/// Returns 0 if the file doesn't exist or can't be read
-pub fn get_system_mem_reserve() -> u32 {
-    let policy_path = "/etc/fairshare/policy.toml";
+/// The partition disk quotas and totals are measured on: the policy's
+/// `disk_partition`, or /home
+pub fn disk_partition(policy: Option<&PolicyConfig>) -> String {
+    policy
+        .and_then(|p| p.defaults.disk_partition.clone())
+        .unwrap_or_else(|| "/home".to_string())
+}
+
+/// Extract the UID from a systemd-logind session record (/run/systemd/sessions/<id>).
+/// Returns None for sessions that are closing or belong to system users.
+fn parse_session_uid(contents: &str) -> Option<u32> {
+    let mut uid = None;
 
-    match fs::read_to_string(policy_path) {
-        Ok(contents) => match toml::from_str::<PolicyConfig>(&contents) {
-            Ok(config) => config.defaults.mem_reserve,
-            Err(_) => 0,
-        },
-        Err(_) => 0,
+    for line in contents.lines() {
+        if let Some(value) = line.strip_prefix("UID=") {
+            uid = value.trim().parse::<u32>().ok();
+        } else if line.trim() == "STATE=closing" {
+            return None;
+        }
     }
+
+    uid.filter(|uid| *uid >= 1000)
 }
 
-/// Read the system disk reserve from policy.toml
-/// Returns 0 if the file doesn't exist or can't be read
-pub fn get_system_disk_reserve() -> u32 {
-    let policy_path = "/etc/fairshare/policy.toml";
+/// Count distinct regular users (UID >= 1000) with an open login session.
+/// Reads the session records kept by systemd-logind; returns 0 if they can't be read.
+pub fn get_active_user_count() -> usize {
+    let entries = match fs::read_dir("/run/systemd/sessions") {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
 
-    match fs::read_to_string(policy_path) {
-        Ok(contents) => match toml::from_str::<PolicyConfig>(&contents) {
-            Ok(config) => config.defaults.disk_reserve,
-            Err(_) => 0,
-        },
-        Err(_) => 0,
+    let mut uids = std::collections::HashSet::new();
+    for entry in entries.flatten() {
+        // Skip the .ref FIFOs logind keeps next to each session file
+        if entry.path().extension().is_some() {
+            continue;
+        }
+        if let Ok(contents) = fs::read_to_string(entry.path()) {
+            if let Some(uid) = parse_session_uid(&contents) {
+                uids.insert(uid);
+            }
+        }
     }
+
+    uids.len()
+}
+
+/// Version of the C library the binary is running against, if it is glibc
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub fn glibc_version() -> Option<String> {
+    // SAFETY: gnu_get_libc_version returns a pointer to a static NUL-terminated string
+    let version = unsafe { std::ffi::CStr::from_ptr(libc::gnu_get_libc_version()) };
+    Some(version.to_string_lossy().into_owned())
 }
 
-/// Read the configured disk partition from policy.toml. Defaults to /home.
-pub fn get_configured_disk_partition() -> Option<String> {
-    let policy_path = "/etc/fairshare/policy.toml";
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+pub fn glibc_version() -> Option<String> {
+    None
+}
+
+/// Login shells that mark an account as unable to log in
+const NOLOGIN_SHELLS: [&str; 4] = [
+    "/usr/sbin/nologin",
+    "/sbin/nologin",
+    "/bin/false",
+    "/usr/bin/false",
+];
+
+/// Parse passwd-formatted content and return (username, UID) for regular login accounts.
+/// Skips system users (UID < 1000), nobody (UID 65534 and above) and accounts whose
+/// shell is nologin or false.
+pub fn parse_login_users(passwd: &str) -> Vec<(String, u32)> {
+    let mut users = vec![];
+
+    for line in passwd.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Format: name:password:UID:GID:GECOS:home:shell
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() < 7 {
+            continue;
+        }
+
+        let uid = match fields[2].parse::<u32>() {
+            Ok(uid) => uid,
+            Err(_) => continue,
+        };
+
+        if !(1000..65534).contains(&uid) || NOLOGIN_SHELLS.contains(&fields[6]) {
+            continue;
+        }
 
-    match fs::read_to_string(policy_path) {
-        Ok(contents) => match toml::from_str::<PolicyConfig>(&contents) {
-            Ok(config) => config.defaults.disk_partition,
-            Err(_) => None,
-        },
-        Err(_) => None,
+        users.push((fields[0].to_string(), uid));
     }
+
+    users
+}
+
+/// Read /etc/passwd and return all regular login accounts as (username, UID)
+pub fn get_login_users() -> io::Result<Vec<(String, u32)>> {
+    let passwd = fs::read_to_string("/etc/passwd")?;
+    Ok(parse_login_users(&passwd))
 }
 
-pub fn get_system_totals() -> SystemTotals {
+pub fn get_system_totals(policy: Option<&PolicyConfig>) -> SystemTotals {
     let mut sys = System::new_all();
     sys.refresh_memory();
     sys.refresh_cpu();
@@ -108,7 +171,7 @@ pub fn get_system_totals() -> SystemTotals {
     let total_cpu = sys.cpus().len();
 
     // Get total size of configured partition (defaults to /home), or root if not found
-    let disk_partition = get_configured_disk_partition().unwrap_or_else(|| "/home".to_string());
+    let disk_partition = disk_partition(policy);
 
     let disks = sysinfo::Disks::new_with_refreshed_list();
     let total_disk_gb = disks
@@ -129,39 +192,181 @@ pub fn get_system_totals() -> SystemTotals {
     }
 }
 
-pub fn get_user_allocations() -> io::Result<Vec<UserAlloc>> {
+/// Preset (cpu, mem, cpu_reserve, mem_reserve) for `admin setup --profile`,
+/// computed as fractions of the machine's totals and never below 1
+pub fn profile_defaults(profile: SetupProfile, totals: &SystemTotals) -> (u32, u32, u32, u32) {
+    // (default fraction, reserve fraction)
+    let (default_frac, reserve_frac) = match profile {
+        SetupProfile::Workstation => (0.5, 0.125),
+        SetupProfile::Hpc => (0.0625, 0.25),
+        SetupProfile::Shared => (0.25, 0.125),
+    };
+
+    let portion = |total: f64, frac: f64| ((total * frac).floor() as u32).max(1);
+    let total_cpu = totals.total_cpu as f64;
+
+    (
+        portion(total_cpu, default_frac),
+        portion(totals.total_mem_gb, default_frac),
+        portion(total_cpu, reserve_frac),
+        portion(totals.total_mem_gb, reserve_frac),
+    )
+}
+
+/// Whole CPUs and GB for `request --cpu-pct/--mem-pct`: the given percentage
+/// of the machine's totals, rounded down but never below 1
+pub fn percent_of_totals(totals: &SystemTotals, cpu_pct: u32, mem_pct: u32) -> (u32, u32) {
+    let portion = |total: f64, pct: u32| ((total * pct as f64 / 100.0).floor() as u32).max(1);
+
+    (
+        portion(totals.total_cpu as f64, cpu_pct),
+        portion(totals.total_mem_gb, mem_pct),
+    )
+}
+
+pub fn get_user_allocations(policy: Option<&PolicyConfig>) -> io::Result<Vec<UserAlloc>> {
     // Query systemd directly for user allocations
-    let mut allocations = get_user_allocations_from_systemd()?;
+    let partition = disk_partition(policy);
+    let mut allocations = get_user_allocations_from_systemd(systemctl_timeout(policy), &partition)?;
 
     // Enrich with disk quotas
     for alloc in &mut allocations {
         if let Ok(uid_int) = alloc.uid.parse::<u32>() {
-            alloc.disk_bytes = crate::systemd::get_user_disk_quota(uid_int).unwrap_or(0);
+            alloc.disk_bytes =
+                crate::systemd::get_user_disk_quota(uid_int, &partition).unwrap_or(0);
         }
     }
 
     Ok(allocations)
 }
 
+/// How long a single systemctl query may take before it is killed
+const DEFAULT_SYSTEMCTL_TIMEOUT_SECS: u64 = 30;
+
+/// The policy's `systemctl_timeout_secs`, or 30 seconds. A policy key rather than
+/// an environment variable because pkexec clears the environment.
+pub fn systemctl_timeout(policy: Option<&PolicyConfig>) -> Duration {
+    Duration::from_secs(
+        policy
+            .and_then(|p| p.defaults.systemctl_timeout_secs)
+            .unwrap_or(DEFAULT_SYSTEMCTL_TIMEOUT_SECS),
+    )
+}
+
+/// Run `systemctl args` and log the argv and exit status at debug level
+/// (`fairshare --debug`). The exit status is left for the caller to judge.
+pub fn spawn_systemctl<S: AsRef<str>>(args: &[S]) -> io::Result<Output> {
+    let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+    log_systemctl(&args, Command::new("systemctl").args(&args).output())
+}
+
+/// Run `systemctl args`; a nonzero exit becomes an error carrying its stderr
+pub fn run_systemctl<S: AsRef<str>>(args: &[S]) -> io::Result<Output> {
+    let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+    check_systemctl(&args, spawn_systemctl(&args)?)
+}
+
+/// `run_systemctl`, killing systemctl if it hasn't exited within `timeout`
+pub fn run_systemctl_with_timeout(args: &[&str], timeout: Duration) -> io::Result<Output> {
+    let output = output_with_timeout(Command::new("systemctl").args(args), timeout);
+    check_systemctl(args, log_systemctl(args, output)?)
+}
+
+fn log_systemctl(args: &[&str], output: io::Result<Output>) -> io::Result<Output> {
+    match &output {
+        Ok(output) => tracing::debug!(argv = ?args, status = ?output.status.code(), "systemctl"),
+        Err(e) => tracing::debug!(argv = ?args, error = %e, "systemctl did not run"),
+    }
+    output
+}
+
+/// `output` of `systemctl args`, or an error carrying its exit code and stderr
+/// if it exited nonzero
+pub fn check_systemctl<S: AsRef<str>>(args: &[S], output: Output) -> io::Result<Output> {
+    if output.status.success() {
+        return Ok(output);
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.trim();
+    let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+    Err(io::Error::other(format!(
+        "systemctl {} failed (exit code: {:?}){}",
+        args.join(" "),
+        output.status.code(),
+        if stderr.is_empty() {
+            String::new()
+        } else {
+            format!(": {}", stderr)
+        }
+    )))
+}
+
+/// Like `Command::output`, but kill the child and return a TimedOut error if it
+/// hasn't exited within `timeout`
+pub fn output_with_timeout(cmd: &mut Command, timeout: Duration) -> io::Result<Output> {
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+
+    // Drain the pipes on their own threads so a chatty child can't block on a
+    // full pipe while we wait for it
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= timeout {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "{:?} did not finish within {}s",
+                    cmd.get_program(),
+                    timeout.as_secs_f64()
+                ),
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn read_in_background<R: Read + Send + 'static>(
+    pipe: Option<R>,
+) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf).ok();
+        }
+        buf
+    })
+}
+
 // Get allocations by querying systemd directly
-fn get_user_allocations_from_systemd() -> io::Result<Vec<UserAlloc>> {
-    let output = Command::new("systemctl")
-        .args([
+fn get_user_allocations_from_systemd(
+    timeout: Duration,
+    partition: &str,
+) -> io::Result<Vec<UserAlloc>> {
+    let output = run_systemctl_with_timeout(
+        &[
             "list-units",
             "--type=slice",
             "--all",
             "--no-legend",
             "--plain",
-        ])
-        .output()
-        .map_err(|e| io::Error::other(format!("Failed to list systemd slices: {}", e)))?;
-
-    if !output.status.success() {
-        return Err(io::Error::other(format!(
-            "systemctl command failed with exit code: {:?}",
-            output.status.code()
-        )));
-    }
+        ],
+        timeout,
+    )
+    .map_err(|e| io::Error::new(e.kind(), format!("Failed to list systemd slices: {}", e)))?;
 
     let mut allocations = vec![];
 
@@ -187,66 +392,119 @@ fn get_user_allocations_from_systemd() -> io::Result<Vec<UserAlloc>> {
             continue;
         }
 
-        let info = Command::new("systemctl")
-            .args([
+        let info = run_systemctl_with_timeout(
+            &[
                 "show",
                 unit_name,
                 "-p",
                 "MemoryMax",
                 "-p",
                 "CPUQuotaPerSecUSec",
-            ])
-            .output()
-            .map_err(|e| {
-                io::Error::other(format!("Failed to get slice info for {}: {}", unit_name, e))
-            })?;
+            ],
+            timeout,
+        )
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to get slice info for {}: {}", unit_name, e),
+            )
+        })?;
 
         let out = String::from_utf8_lossy(&info.stdout);
-        let mut mem_bytes = 0;
-        let mut cpu_quota = 0.0;
 
         let uid_val = uid.parse::<u32>().unwrap_or(0);
-        let disk_bytes = crate::systemd::get_user_disk_quota(uid_val).unwrap_or(0);
+        let disk_bytes = crate::systemd::get_user_disk_quota(uid_val, partition).unwrap_or(0);
 
-        for l in out.lines() {
-            if l.starts_with("MemoryMax=") {
-                if let Some(value_str) = l.strip_prefix("MemoryMax=") {
-                    mem_bytes = value_str.parse::<u64>().unwrap_or(0);
-                }
-            } else if l.starts_with("CPUQuotaPerSecUSec=") {
-                if let Some(quota_str) = l.strip_prefix("CPUQuotaPerSecUSec=") {
-                    if let Some(sec_str) = quota_str.strip_suffix('s') {
-                        if let Ok(seconds) = sec_str.parse::<f64>() {
-                            // Convert seconds to percentage (1s = 100%, 2s = 200%, etc)
-                            cpu_quota = seconds * 100.0;
-                        }
+        allocations.push(parse_slice_show(&uid, &out, disk_bytes));
+    }
+
+    Ok(allocations)
+}
+
+/// Every user slice's CPU and memory limits from a single `systemctl show`
+/// call, instead of one call per slice. Disk quotas come from quotactl, which
+/// doesn't spawn a process per user.
+pub fn get_user_allocations_aggregate(policy: Option<&PolicyConfig>) -> io::Result<Vec<UserAlloc>> {
+    let output = run_systemctl_with_timeout(
+        &[
+            "show",
+            "user-*.slice",
+            "-p",
+            "Id",
+            "-p",
+            "MemoryMax",
+            "-p",
+            "CPUQuotaPerSecUSec",
+        ],
+        systemctl_timeout(policy),
+    )
+    .map_err(|e| io::Error::new(e.kind(), format!("Failed to show user slices: {}", e)))?;
+
+    let partition = disk_partition(policy);
+    let mut allocations = parse_slices_show(&String::from_utf8_lossy(&output.stdout));
+    for alloc in &mut allocations {
+        if let Ok(uid) = alloc.uid.parse::<u32>() {
+            alloc.disk_bytes = crate::systemd::get_user_disk_quota(uid, &partition).unwrap_or(0);
+        }
+    }
+
+    Ok(allocations)
+}
+
+/// Split multi-unit `systemctl show -p Id ...` output (one blank-line separated
+/// block per unit) into allocations, skipping root and non-user slices
+pub fn parse_slices_show(output: &str) -> Vec<UserAlloc> {
+    output
+        .split("\n\n")
+        .filter_map(|block| {
+            let id = block.lines().find_map(|l| l.strip_prefix("Id="))?;
+            let uid = parse_uid_from_slice(id.trim())?;
+            if uid == "0" {
+                return None;
+            }
+            Some(parse_slice_show(&uid, block, 0))
+        })
+        .collect()
+}
+
+/// Build a `UserAlloc` from `systemctl show -p MemoryMax -p CPUQuotaPerSecUSec`
+/// output. Unset ("infinity") or unparsable limits become 0.
+pub fn parse_slice_show(uid: &str, show_output: &str, disk_bytes: u64) -> UserAlloc {
+    let mut mem_bytes = 0;
+    let mut cpu_quota = 0.0;
+
+    for l in show_output.lines() {
+        if l.starts_with("MemoryMax=") {
+            if let Some(value_str) = l.strip_prefix("MemoryMax=") {
+                mem_bytes = value_str.parse::<u64>().unwrap_or(0);
+            }
+        } else if l.starts_with("CPUQuotaPerSecUSec=") {
+            if let Some(quota_str) = l.strip_prefix("CPUQuotaPerSecUSec=") {
+                if let Some(sec_str) = quota_str.strip_suffix('s') {
+                    if let Ok(seconds) = sec_str.parse::<f64>() {
+                        // Convert seconds to percentage (1s = 100%, 2s = 200%, etc)
+                        cpu_quota = seconds * 100.0;
                     }
                 }
             }
         }
-
-        allocations.push(UserAlloc {
-            uid,
-            cpu_quota,
-            mem_bytes,
-            disk_bytes,
-        });
     }
 
-    Ok(allocations)
+    UserAlloc {
+        uid: uid.to_string(),
+        cpu_quota,
+        mem_bytes,
+        disk_bytes,
+    }
 }
 
 fn parse_uid_from_slice(slice_name: &str) -> Option<String> {
-    // Expected format: "user-1000.slice"
-    let parts: Vec<&str> = slice_name.split('-').collect();
-    if parts.len() != 2 || parts[0] != "user" {
-        return None;
-    }
-
-    let uid_str = parts[1].trim_end_matches(".slice");
+    // Expected format: "user-1000.slice". Match the exact prefix and suffix rather
+    // than splitting on '-', so escaped (\x2d) or extra hyphens never match
+    let uid_str = slice_name.strip_prefix("user-")?.strip_suffix(".slice")?;
 
     // Validate it's only digits
-    if !uid_str.chars().all(|c| c.is_ascii_digit()) {
+    if uid_str.is_empty() || !uid_str.chars().all(|c| c.is_ascii_digit()) {
         return None;
     }
 
@@ -256,78 +514,504 @@ fn parse_uid_from_slice(slice_name: &str) -> Option<String> {
     Some(uid_str.to_string())
 }
 
+/// Where a user's allocation drop-in lives: "Runtime" if under /run (dropped at
+/// reboot), "Persistent" if under /etc, or None if the user has no drop-in
+pub fn get_allocation_scope(uid: &str) -> Option<&'static str> {
+    allocation_scope_in(
+        Path::new("/etc/systemd/system.control"),
+        Path::new("/run/systemd/system.control"),
+        uid,
+    )
+}
+
+fn allocation_scope_in(
+    persistent_dir: &Path,
+    runtime_dir: &Path,
+    uid: &str,
+) -> Option<&'static str> {
+    let dropin_dir = format!("user-{}.slice.d", uid);
+
+    // Runtime drop-ins take precedence over persistent ones in systemd
+    if runtime_dir.join(&dropin_dir).is_dir() {
+        Some("Runtime")
+    } else if persistent_dir.join(&dropin_dir).is_dir() {
+        Some("Persistent")
+    } else {
+        None
+    }
+}
+
+/// Policy and host inputs to the availability math, gathered once so the
+/// calculations themselves are pure
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AllocationLimits {
+    /// System reserves (cpu, mem_gb, disk_gb)
+    pub reserves: (u32, u32, u32),
+    /// Per-user default (cpu, mem_gb, disk_gb) every login user counts as holding
+    pub defaults: (u32, u32, u32),
+    /// Active user count when dynamic fair share is enabled, None when disabled
+    pub fair_share_users: Option<usize>,
+    /// UIDs of regular login accounts
+    pub login_uids: Vec<u32>,
+    /// Largest memory (GB) per requested CPU, None for no ratio check
+    pub max_mem_per_cpu_gb: Option<u32>,
+    /// (cpu, mem_gb) set aside by scheduled holds whose window is open now
+    pub holds: (u32, u32),
+    /// Percent of total (cpu, mem) that must stay free; 0 disables the floor
+    pub min_free_pct: (u32, u32),
+    /// How fractional availability is turned into whole CPUs and GB
+    pub rounding: Rounding,
+}
+
+impl AllocationLimits {
+    /// Build limits from a policy plus the host's login accounts and active sessions
+    pub fn new(
+        policy: Option<&PolicyConfig>,
+        login_uids: Vec<u32>,
+        active_users: impl FnOnce() -> usize,
+    ) -> Self {
+        let defaults = policy.map(|p| &p.defaults);
+        AllocationLimits {
+            reserves: defaults.map_or((0, 0, 0), |d| {
+                (d.cpu_reserve, d.mem_reserve, d.disk_reserve)
+            }),
+            defaults: defaults.map_or((0, 0, 0), |d| (d.cpu, d.mem, d.disk)),
+            fair_share_users: defaults
+                .filter(|d| d.dynamic_fair_share)
+                .map(|_| active_users()),
+            login_uids,
+            max_mem_per_cpu_gb: defaults.and_then(|d| d.max_mem_per_cpu_gb),
+            holds: policy.map_or((0, 0), |p| {
+                active_hold_totals(&p.holds, local_minutes_of_day())
+            }),
+            min_free_pct: defaults.map_or((0, 0), |d| (d.min_free_cpu_pct, d.min_free_mem_pct)),
+            rounding: defaults.map_or(Rounding::default(), |d| d.rounding),
+        }
+    }
+}
+
+/// Total (cpu, mem_gb) of the holds whose window contains `now` (minutes since
+/// local midnight)
+pub fn active_hold_totals(holds: &[ScheduledHold], now: u32) -> (u32, u32) {
+    holds
+        .iter()
+        .filter(|hold| hold.is_active_at(now))
+        .fold((0, 0), |(cpu, mem), hold| (cpu + hold.cpu, mem + hold.mem))
+}
+
+/// Minutes since midnight in the machine's local time zone
+pub fn local_minutes_of_day() -> u32 {
+    // SAFETY: localtime_r only writes to the tm we pass it
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return 0;
+        }
+        (tm.tm_hour * 60 + tm.tm_min) as u32
+    }
+}
+
 /// Calculate all available resources for the requesting user
 /// Returns (available_cpu, available_mem_gb) taking into account:
 /// - System reserves
-/// - Other users' allocations
+/// - Other users' allocations (at least the policy default for every login user)
 /// - Requesting user's current allocation (delta-based)
 pub fn calculate_available_resources(
     totals: &SystemTotals,
     allocations: &[UserAlloc],
     requesting_user_uid: Option<&str>,
+    limits: &AllocationLimits,
 ) -> (u32, u32, u32) {
-    // Get system reserves
-    let cpu_reserve = get_system_cpu_reserve() as f64;
-    let mem_reserve = get_system_mem_reserve() as f64;
-    let disk_reserve = get_system_disk_reserve() as f64;
-
-    // Calculate currently used resources from all users
-    let used_cpu: f64 = allocations.iter().map(|a| a.cpu_quota / 100.0).sum();
-    let used_mem: f64 = allocations
-        .iter()
-        .map(|a| a.mem_bytes as f64 / 1_000_000_000.0)
-        .sum();
-    let used_disk: f64 = allocations
-        .iter()
-        .map(|a| a.disk_bytes as f64 / 1_000_000_000.0)
-        .sum();
-
-    // If the requesting user already has an allocation, subtract it from used resources
-    // This allows us to check if the NET INCREASE fits, not the entire new request
-    let (adjusted_used_cpu, adjusted_used_mem, adjusted_used_disk) =
-        if let Some(uid) = requesting_user_uid {
-            let current_user_alloc = allocations.iter().find(|a| a.uid == uid);
-            if let Some(alloc) = current_user_alloc {
-                let current_cpu = alloc.cpu_quota / 100.0;
-                let current_mem = alloc.mem_bytes as f64 / 1_000_000_000.0;
-                let current_disk = alloc.disk_bytes as f64 / 1_000_000_000.0;
-                (
-                    used_cpu - current_cpu,
-                    used_mem - current_mem,
-                    used_disk - current_disk,
-                )
-            } else {
-                (used_cpu, used_mem, used_disk)
+    compute_availability(totals, allocations, limits, requesting_user_uid).available()
+}
+
+/// Turn what is available into a `request --all` allocation. `available` already
+/// includes the user's own `held` (cpu, mem_gb), which the grant never drops below
+/// even when other users have oversubscribed the machine. Fails if no CPU or
+/// no memory is left, since CPUQuota=0% or MemoryMax=0 would freeze the user's
+/// processes rather than leave them unlimited.
+pub fn all_available_request(
+    available: (u32, u32, u32),
+    held: (u32, u32),
+) -> Result<(u32, u32, u32), &'static str> {
+    match (
+        available.0.max(held.0),
+        available.1.max(held.1),
+        available.2,
+    ) {
+        (0, 0, _) => Err("No resources available to allocate."),
+        (0, _, _) => Err("No CPU available to allocate."),
+        (_, 0, _) => Err("No memory available to allocate."),
+        grant => Ok(grant),
+    }
+}
+
+/// Whole CPUs and GB of memory `uid` currently holds, (0, 0) without a slice limit
+pub fn current_holding(allocations: &[UserAlloc], uid: Option<&str>) -> (u32, u32) {
+    uid.and_then(|uid| allocations.iter().find(|a| a.uid == uid))
+        .map_or((0, 0), |a| {
+            (
+                (a.cpu_quota / 100.0).floor() as u32,
+                (a.mem_bytes / 1_000_000_000) as u32,
+            )
+        })
+}
+
+/// Success message for `request --all`, spelling out that the user's previous
+/// allocation was rolled into the new total
+pub fn all_request_message(held: (u32, u32), grant: (u32, u32)) -> Option<String> {
+    if held == (0, 0) {
+        return None;
+    }
+    Some(format!(
+        "Reclaimed your previous {} CPU(s) and {}G RAM and allocated a total of {} CPU(s) and {}G RAM.",
+        held.0, held.1, grant.0, grant.1
+    ))
+}
+
+/// Shrink a `request --best-effort` to what is available: each of (cpu, mem_gb,
+/// disk_gb) becomes min(requested, available). Fails if the CPU or memory grant
+/// would fall below the `min_cpu`/`min_mem` floor.
+pub fn best_effort_grant(
+    requested: (u32, f64, u32),
+    available: (u32, u32, u32),
+    min_cpu: u32,
+    min_mem: u32,
+) -> Result<(u32, f64, u32), String> {
+    let grant = (
+        requested.0.min(available.0),
+        requested.1.min(available.1 as f64),
+        requested.2.min(available.2),
+    );
+
+    if grant.0 < min_cpu {
+        return Err(format!(
+            "Only {} CPU(s) available, below the minimum of {}.",
+            grant.0, min_cpu
+        ));
+    }
+    if grant.1 < min_mem as f64 {
+        return Err(format!(
+            "Only {}G RAM available, below the minimum of {}G.",
+            grant.1, min_mem
+        ));
+    }
+
+    Ok(grant)
+}
+
+/// `request --interactive`: prompt on `output` for CPUs and memory, offering the
+/// `available` amounts as defaults, until `fits` accepts the pair, then ask for
+/// confirmation. Returns Ok(None) if the user declines, and an error if `input`
+/// ends before the wizard is done.
+pub fn request_wizard<R, W, F>(
+    input: &mut R,
+    output: &mut W,
+    available: (u32, u32, u32),
+    mut fits: F,
+) -> io::Result<Option<(u32, u32)>>
+where
+    R: BufRead,
+    W: Write,
+    F: FnMut(u32, u32) -> bool,
+{
+    writeln!(
+        output,
+        "Available: {} CPU(s), {}G RAM",
+        available.0, available.1
+    )?;
+
+    loop {
+        let cpu = prompt_number(input, output, "CPUs", available.0, MIN_CPU, MAX_CPU)?;
+        let mem = prompt_number(input, output, "Memory (GB)", available.1, MIN_MEM, MAX_MEM)?;
+
+        if !fits(cpu, mem) {
+            writeln!(
+                output,
+                "{} CPU(s) and {}G RAM exceeds what is available; try a smaller request.",
+                cpu, mem
+            )?;
+            continue;
+        }
+
+        write!(output, "Request {} CPU(s) and {}G RAM? [y/N]: ", cpu, mem)?;
+        output.flush()?;
+        let answer = read_answer(input)?;
+        let confirmed = answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes");
+        return Ok(confirmed.then_some((cpu, mem)));
+    }
+}
+
+/// Prompt for a number in `min..=max` until one is entered; an empty line takes
+/// `default` (clamped into range, since nothing may be available)
+fn prompt_number<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    label: &str,
+    default: u32,
+    min: u32,
+    max: u32,
+) -> io::Result<u32> {
+    let default = default.clamp(min, max);
+
+    loop {
+        write!(output, "{} [{}]: ", label, default)?;
+        output.flush()?;
+
+        let answer = read_answer(input)?;
+        if answer.is_empty() {
+            return Ok(default);
+        }
+        match answer.parse::<u32>() {
+            Ok(n) if (min..=max).contains(&n) => return Ok(n),
+            _ => writeln!(output, "Enter a whole number from {} to {}.", min, max)?,
+        }
+    }
+}
+
+fn read_answer<R: BufRead>(input: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "input ended before the request was confirmed",
+        ));
+    }
+    Ok(line.trim().to_string())
+}
+
+/// Copy of `allocations` with each (uid, cpu, mem_gb) applied as if that user had run
+/// `fairshare request --cpu CPU --mem MEM`: an existing allocation is replaced, a new
+/// user is added. Nothing is written to systemd.
+pub fn simulate_allocations(
+    allocations: &[UserAlloc],
+    requests: &[(u32, u32, u32)],
+) -> Vec<UserAlloc> {
+    let mut simulated = allocations.to_vec();
+
+    for &(uid, cpu, mem) in requests {
+        let uid = uid.to_string();
+        let cpu_quota = cpu as f64 * 100.0;
+        let mem_bytes = mem as u64 * 1_000_000_000;
+
+        match simulated.iter_mut().find(|a| a.uid == uid) {
+            Some(alloc) => {
+                alloc.cpu_quota = cpu_quota;
+                alloc.mem_bytes = mem_bytes;
             }
-        } else {
-            (used_cpu, used_mem, used_disk)
-        };
+            None => simulated.push(UserAlloc {
+                uid,
+                cpu_quota,
+                mem_bytes,
+                disk_bytes: 0,
+            }),
+        }
+    }
 
-    // Subtract the system reserves from available resources
-    let available_cpu = totals.total_cpu as f64 - adjusted_used_cpu - cpu_reserve;
-    let available_mem = totals.total_mem_gb - adjusted_used_mem - mem_reserve;
-    let available_disk = totals.total_disk_gb - adjusted_used_disk - disk_reserve;
+    simulated
+}
 
-    // Return as u32, ensuring we don't return negative values
-    let available_cpu_u32 = if available_cpu > 0.0 {
-        available_cpu.floor() as u32
-    } else {
-        0
-    };
-    let available_mem_u32 = if available_mem > 0.0 {
-        available_mem.floor() as u32
-    } else {
-        0
+/// One user's line in an `admin apply --from-file` batch
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchEntry {
+    cpu: u32,
+    mem: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchFile {
+    users: BTreeMap<String, BatchEntry>,
+}
+
+/// Parse an `admin apply --from-file` batch: a `[users]` table mapping each
+/// username or UID to `{ cpu = N, mem = GB }`. Returns (user, cpu, mem) in
+/// user order, after checking every value against the request limits.
+pub fn parse_batch_file(contents: &str) -> io::Result<Vec<(String, u32, u32)>> {
+    let batch = toml::from_str::<BatchFile>(contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    batch
+        .users
+        .into_iter()
+        .map(|(user, entry)| checked_batch_entry(user, entry))
+        .collect()
+}
+
+/// Parse an `admin apply --json-stdin` batch: a JSON array of `{uid, cpu, mem}`.
+/// Returns the same (user, cpu, mem) as `parse_batch_file`, in array order.
+pub fn parse_batch_json(contents: &str) -> io::Result<Vec<(String, u32, u32)>> {
+    let invalid = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let batch = serde_json::from_str::<Vec<serde_json::Map<String, serde_json::Value>>>(contents)
+        .map_err(invalid)?;
+
+    batch
+        .into_iter()
+        .map(|mut fields| {
+            // Whatever is left beside the UID must be a `BatchEntry`, unknown keys included
+            let uid = fields
+                .remove("uid")
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing field `uid`"))?;
+            let uid = serde_json::from_value::<u32>(uid).map_err(invalid)?;
+            let entry = serde_json::from_value::<BatchEntry>(serde_json::Value::Object(fields))
+                .map_err(invalid)?;
+            checked_batch_entry(uid.to_string(), entry)
+        })
+        .collect()
+}
+
+/// Check one batch entry against the request limits
+fn checked_batch_entry(user: String, entry: BatchEntry) -> io::Result<(String, u32, u32)> {
+    if !(MIN_CPU..=MAX_CPU).contains(&entry.cpu) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}: cpu {} must be {}-{}",
+                user, entry.cpu, MIN_CPU, MAX_CPU
+            ),
+        ));
+    }
+    if !(MIN_MEM..=MAX_MEM).contains(&entry.mem) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}: mem {} must be {}-{}",
+                user, entry.mem, MIN_MEM, MAX_MEM
+            ),
+        ));
+    }
+
+    Ok((user, entry.cpu, entry.mem))
+}
+
+/// Turn batch usernames into UIDs with `resolve`, failing with every user
+/// that couldn't be resolved so the file can be fixed in one go
+pub fn resolve_batch_users<F>(
+    entries: &[(String, u32, u32)],
+    resolve: F,
+) -> io::Result<Vec<(u32, u32, u32)>>
+where
+    F: Fn(&str) -> io::Result<u32>,
+{
+    let mut resolved = vec![];
+    let mut unknown = vec![];
+
+    for (user, cpu, mem) in entries {
+        match resolve(user) {
+            Ok(uid) => resolved.push((uid, *cpu, *mem)),
+            Err(_) => unknown.push(user.as_str()),
+        }
+    }
+
+    if !unknown.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unknown user(s): {}", unknown.join(", ")),
+        ));
+    }
+
+    // The same user under two names (e.g. alice and her UID) would be applied twice
+    let mut repeated: Vec<String> = resolved
+        .iter()
+        .enumerate()
+        .filter(|(i, (uid, _, _))| resolved[..*i].iter().any(|(earlier, _, _)| earlier == uid))
+        .map(|(_, (uid, _, _))| uid.to_string())
+        .collect();
+    repeated.dedup();
+    if !repeated.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("UID(s) listed more than once: {}", repeated.join(", ")),
+        ));
+    }
+
+    Ok(resolved)
+}
+
+/// A batch entry `validate_batch` refused, by its position in the batch
+#[derive(Debug, PartialEq)]
+pub struct Rejection {
+    pub index: usize,
+    pub uid: u32,
+    pub reason: String,
+}
+
+/// Whether a whole batch fits at once: each (uid, cpu, mem) replaces that
+/// user's allocation and gets the same policy checks as `check_request` (memory
+/// per CPU, dynamic fair share ceiling), and the machine must still cover every
+/// user plus the reserves and active holds. When it doesn't, the entries are
+/// added up in order and every one that takes the running total over capacity
+/// (or further over) is rejected with the shortfall it leaves.
+pub fn validate_batch(
+    totals: &SystemTotals,
+    allocations: &[UserAlloc],
+    batch: &[(u32, u32, u32)],
+    limits: &AllocationLimits,
+) -> Result<(), Vec<Rejection>> {
+    let shortfall = |applied: &[(u32, u32, u32)]| {
+        let simulated = simulate_allocations(allocations, applied);
+        let (cpu_left, mem_left, _) =
+            compute_availability(totals, &simulated, limits, None).before_ceiling();
+        ((-cpu_left).max(0.0), (-mem_left).max(0.0))
     };
-    let available_disk_u32 = if available_disk > 0.0 {
-        available_disk.floor() as u32
+
+    let simulated = simulate_allocations(allocations, batch);
+    let mut rejections = vec![];
+    for (index, &(uid, cpu, mem)) in batch.iter().enumerate() {
+        let availability = compute_availability(totals, &simulated, limits, Some(&uid.to_string()));
+        // Capacity is judged for the batch as a whole below
+        let reason = match availability.check(cpu, mem as f64, 0.0) {
+            Err(Refusal::MemPerCpu(msg)) => msg,
+            Err(Refusal::FairShare) => fair_share_reason(&availability, limits),
+            Ok(()) | Err(Refusal::Insufficient) => continue,
+        };
+        rejections.push(Rejection { index, uid, reason });
+    }
+
+    if shortfall(batch) != (0.0, 0.0) {
+        let mut previous = shortfall(&[]);
+        for (index, &(uid, _, _)) in batch.iter().enumerate() {
+            let (cpu_over, mem_over) = shortfall(&batch[..=index]);
+            if cpu_over > previous.0 || mem_over > previous.1 {
+                rejections.push(Rejection {
+                    index,
+                    uid,
+                    reason: format!(
+                        "takes the batch over available resources by {} CPUs and {}G RAM",
+                        format_decimal(cpu_over),
+                        format_decimal(mem_over)
+                    ),
+                });
+            }
+            previous = (cpu_over, mem_over);
+        }
+    }
+
+    if rejections.is_empty() {
+        Ok(())
     } else {
-        0
-    };
+        rejections.sort_by_key(|r| r.index);
+        Err(rejections)
+    }
+}
 
-    (available_cpu_u32, available_mem_u32, available_disk_u32)
+/// One element of the JSON array `admin apply --json-stdin` prints, in batch order.
+/// Field names are a stable interface for orchestration; add fields rather than renaming them.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BatchResult {
+    pub uid: u32,
+    pub cpu: u32,
+    pub mem: u32,
+    pub applied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
+/// Whether `req_cpu`, `req_mem_gb` and `req_disk_gb` fit for `requesting_user_uid`.
+/// A refusal carries the policy limit behind it (memory per CPU or dynamic fair
+/// share) for the caller to show, or None when there simply isn't enough left.
 pub fn check_request(
     totals: &SystemTotals,
     allocations: &[UserAlloc],
@@ -335,52 +1019,113 @@ pub fn check_request(
     req_mem_gb: &str,
     req_disk_gb: u32,
     requesting_user_uid: Option<&str>,
-) -> bool {
-    // Get system reserves
-    let cpu_reserve = get_system_cpu_reserve() as f64;
-    let mem_reserve = get_system_mem_reserve() as f64;
-    let disk_reserve = get_system_disk_reserve() as f64;
-
-    // Calculate currently used resources from all users
-    let used_cpu: f64 = allocations.iter().map(|a| a.cpu_quota / 100.0).sum();
-    let used_mem: f64 = allocations
-        .iter()
-        .map(|a| a.mem_bytes as f64 / 1_000_000_000.0)
-        .sum();
-    let used_disk: f64 = allocations
-        .iter()
-        .map(|a| a.disk_bytes as f64 / 1_000_000_000.0)
-        .sum();
-
-    // If the requesting user already has an allocation, subtract it from used resources
-    // This allows us to check if the NET INCREASE fits, not the entire new request
-    let (adjusted_used_cpu, adjusted_used_mem, adjusted_used_disk) =
-        if let Some(uid) = requesting_user_uid {
-            let current_user_alloc = allocations.iter().find(|a| a.uid == uid);
-            if let Some(alloc) = current_user_alloc {
-                let current_cpu = alloc.cpu_quota / 100.0;
-                let current_mem = alloc.mem_bytes as f64 / 1_000_000_000.0;
-                let current_disk = alloc.disk_bytes as f64 / 1_000_000_000.0;
-                (
-                    used_cpu - current_cpu,
-                    used_mem - current_mem,
-                    used_disk - current_disk,
-                )
-            } else {
-                (used_cpu, used_mem, used_disk)
-            }
-        } else {
-            (used_cpu, used_mem, used_disk)
+    limits: &AllocationLimits,
+) -> Result<(), Option<String>> {
+    let availability = compute_availability(totals, allocations, limits, requesting_user_uid);
+
+    match availability.check(req_cpu, parse_mem_gb(req_mem_gb), req_disk_gb as f64) {
+        Ok(()) => Ok(()),
+        Err(Refusal::MemPerCpu(msg)) => Err(Some(msg)),
+        Err(Refusal::FairShare) => Err(Some(fair_share_reason(&availability, limits))),
+        Err(Refusal::Insufficient) => Err(None),
+    }
+}
+
+/// `check_request` for a grant that never drops below the (cpu, mem_gb) the
+/// user already holds, as `request --all` makes. Keeping what is held takes
+/// nothing from anyone, so a dimension within `held` skips the capacity
+/// comparison; the memory-per-CPU and fair share checks apply in full.
+#[allow(clippy::too_many_arguments)]
+pub fn check_request_keeping(
+    totals: &SystemTotals,
+    allocations: &[UserAlloc],
+    req_cpu: u32,
+    req_mem_gb: &str,
+    req_disk_gb: u32,
+    requesting_user_uid: Option<&str>,
+    limits: &AllocationLimits,
+    held: (u32, u32),
+) -> Result<(), Option<String>> {
+    check_request(
+        totals,
+        allocations,
+        req_cpu,
+        req_mem_gb,
+        req_disk_gb,
+        requesting_user_uid,
+        limits,
+    )
+    .or_else(|reason| {
+        if reason.is_some() {
+            return Err(reason);
+        }
+        // Only capacity refused it: compare again with just the part beyond `held`
+        let capacity = Availability {
+            ceiling: None,
+            max_mem_per_cpu_gb: None,
+            ..compute_availability(totals, allocations, limits, requesting_user_uid)
         };
+        let req_mem_gb = parse_mem_gb(req_mem_gb);
+        capacity
+            .check(
+                if req_cpu <= held.0 { 0 } else { req_cpu },
+                if req_mem_gb <= held.1 as f64 {
+                    0.0
+                } else {
+                    req_mem_gb
+                },
+                req_disk_gb as f64,
+            )
+            .map_err(|_| None)
+    })
+}
+
+/// Explanation for a `Refusal::FairShare`: the active users and per-user ceiling
+fn fair_share_reason(availability: &Availability, limits: &AllocationLimits) -> String {
+    let (cpu_ceiling, mem_ceiling, disk_ceiling) = availability.ceiling.unwrap_or_default();
+    format!(
+        "Dynamic fair share: {} active user(s), per-user limit is {} CPUs, {}G RAM, {}G Disk",
+        limits.fair_share_users.unwrap_or(1).max(1),
+        format_decimal(cpu_ceiling),
+        format_decimal(mem_ceiling),
+        format_decimal(disk_ceiling)
+    )
+}
+
+/// Poll `check` every `interval` until the request fits, a policy limit refuses
+/// it or `timeout` elapses. `check` answers as `check_request` does, and only
+/// Err(None), too little free for now, is worth waiting on. `sleep` is injected
+/// so the loop can be tested without waiting.
+/// Returns Ok(true) if the request fits, Ok(false) on timeout and the policy's
+/// reason if it refuses. A `timeout` of None waits forever.
+pub fn wait_for_resources<F, S>(
+    mut check: F,
+    timeout: Option<Duration>,
+    interval: Duration,
+    mut sleep: S,
+) -> Result<bool, String>
+where
+    F: FnMut(Duration) -> Result<(), Option<String>>,
+    S: FnMut(Duration),
+{
+    let mut elapsed = Duration::ZERO;
+
+    loop {
+        match check(elapsed) {
+            Ok(()) => return Ok(true),
+            Err(Some(reason)) => return Err(reason),
+            Err(None) => {}
+        }
 
-    // Subtract the system reserves from available resources
-    let available_cpu = totals.total_cpu as f64 - adjusted_used_cpu - cpu_reserve;
-    let available_mem = totals.total_mem_gb - adjusted_used_mem - mem_reserve;
-    let available_disk = totals.total_disk_gb - adjusted_used_disk - disk_reserve;
-    let req_mem = parse_mem_gb(req_mem_gb);
-    let req_disk = req_disk_gb as f64;
+        if let Some(limit) = timeout {
+            if elapsed >= limit {
+                return Ok(false);
+            }
+        }
 
-    req_cpu as f64 <= available_cpu && req_mem <= available_mem && req_disk <= available_disk
+        sleep(interval);
+        elapsed += interval;
+    }
 }
 
 fn parse_mem_gb(mem: &str) -> f64 {
@@ -394,21 +1139,99 @@ fn parse_mem_gb(mem: &str) -> f64 {
     }
 }
 
-/// Get username from UID, returns None if user doesn't exist
+/// Get username from UID, returns None if user doesn't exist or the lookup failed
 pub fn get_username_from_uid(uid_str: &str) -> Option<String> {
     let uid_num: uid_t = uid_str.parse().ok()?;
-    get_user_by_uid(uid_num).map(|user| user.name().to_string_lossy().into_owned())
+    match lookup_username(uid_num) {
+        UsernameLookup::Found(name) => Some(name),
+        UsernameLookup::NoSuchUser | UsernameLookup::LookupError => None,
+    }
 }
 
-/// Get UID from username or UID string
-/// Accepts either a username (e.g., "john") or a UID string (e.g., "1000")
-/// Returns the UID if the user exists, or an error if not found
-pub fn get_uid_from_user_string(user: &str) -> io::Result<u32> {
-    // First try to parse as a UID number
-    if let Ok(uid) = user.parse::<u32>() {
-        // Verify the UID exists on the system
-        if get_user_by_uid(uid).is_some() {
-            return Ok(uid);
+/// Outcome of resolving a UID through NSS
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UsernameLookup {
+    Found(String),
+    /// NSS answered that the UID doesn't exist, e.g. the account was deleted
+    NoSuchUser,
+    /// NSS couldn't answer (LDAP/SSSD down) and the name wasn't cached
+    LookupError,
+}
+
+static USERNAME_CACHE: std::sync::Mutex<BTreeMap<u32, String>> =
+    std::sync::Mutex::new(BTreeMap::new());
+
+/// Resolve `uid`, remembering successful answers so a transient NSS failure
+/// later in the process still shows the name instead of flapping to the UID
+pub fn lookup_username(uid: u32) -> UsernameLookup {
+    let mut cache = USERNAME_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    resolve_username(uid, getpwuid_name, &mut cache)
+}
+
+/// `lookup_username` with the passwd query and cache supplied by the caller
+pub fn resolve_username<F>(uid: u32, query: F, cache: &mut BTreeMap<u32, String>) -> UsernameLookup
+where
+    F: FnOnce(u32) -> io::Result<Option<String>>,
+{
+    match query(uid) {
+        Ok(Some(name)) => {
+            cache.insert(uid, name.clone());
+            UsernameLookup::Found(name)
+        }
+        Ok(None) => {
+            cache.remove(&uid);
+            UsernameLookup::NoSuchUser
+        }
+        Err(_) => cache.get(&uid).map_or(UsernameLookup::LookupError, |name| {
+            UsernameLookup::Found(name.clone())
+        }),
+    }
+}
+
+/// The passwd entry name for `uid`. Unlike `users::get_user_by_uid`, this tells
+/// "no such user" (Ok(None)) apart from an NSS failure (Err).
+fn getpwuid_name(uid: u32) -> io::Result<Option<String>> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+        let rc =
+            unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+
+        match rc {
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            // getpwuid_r(3) lists these as ways of saying the UID wasn't found
+            0 | libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM if result.is_null() => {
+                return Ok(None)
+            }
+            0 => {
+                let name = unsafe { std::ffi::CStr::from_ptr(pwd.pw_name) };
+                return Ok(Some(name.to_string_lossy().into_owned()));
+            }
+            rc => return Err(io::Error::from_raw_os_error(rc)),
+        }
+    }
+}
+
+/// Username column text and colour for the status table. A UID with no passwd
+/// entry is flagged since its allocation is likely stale and can be released.
+fn username_cell(uid: &str, lookup: &UsernameLookup) -> (String, Color) {
+    match lookup {
+        UsernameLookup::Found(name) => (name.clone(), Color::White),
+        UsernameLookup::NoSuchUser => (format!("({}) deleted user?", uid), Color::Red),
+        UsernameLookup::LookupError => (format!("({}) lookup failed", uid), Color::DarkGrey),
+    }
+}
+
+/// Get UID from username or UID string
+/// Accepts either a username (e.g., "john") or a UID string (e.g., "1000")
+/// Returns the UID if the user exists, or an error if not found
+pub fn get_uid_from_user_string(user: &str) -> io::Result<u32> {
+    // First try to parse as a UID number
+    if let Ok(uid) = user.parse::<u32>() {
+        // Verify the UID exists on the system
+        if get_user_by_uid(uid).is_some() {
+            return Ok(uid);
         } else {
             return Err(io::Error::new(
                 io::ErrorKind::NotFound,
@@ -427,406 +1250,1486 @@ pub fn get_uid_from_user_string(user: &str) -> io::Result<u32> {
     }
 }
 
-pub fn print_status(totals: &SystemTotals, allocations: &[UserAlloc]) {
-    // Get system reserves
-    let cpu_reserve = get_system_cpu_reserve() as f64;
-    let mem_reserve = get_system_mem_reserve() as f64;
-    let disk_reserve = get_system_disk_reserve() as f64;
-
-    // Calculate usage
-    let used_cpu: f64 = allocations.iter().map(|a| a.cpu_quota / 100.0).sum();
-    let used_mem: f64 = allocations
-        .iter()
-        .map(|a| a.mem_bytes as f64 / 1_000_000_000.0)
-        .sum();
-    let used_disk: f64 = allocations
+/// Describe how far allocations plus reserves exceed the machine, or None if they fit.
+/// `used` and `reserve` are (cpu, mem_gb, disk_gb).
+fn oversubscription_warning(
+    totals: &SystemTotals,
+    used: (f64, f64, f64),
+    reserve: (f64, f64, f64),
+) -> Option<String> {
+    let resources = [
+        ("CPUs", totals.total_cpu as f64, used.0 + reserve.0, ""),
+        ("RAM", totals.total_mem_gb, used.1 + reserve.1, "G"),
+        ("Disk", totals.total_disk_gb, used.2 + reserve.2, "G"),
+    ];
+
+    let overages: Vec<String> = resources
         .iter()
-        .map(|a| a.disk_bytes as f64 / 1_000_000_000.0)
-        .sum();
-
-    let available_cpu = totals.total_cpu as f64 - used_cpu - cpu_reserve;
-    let available_mem = totals.total_mem_gb - used_mem - mem_reserve;
-    let available_disk = totals.total_disk_gb - used_disk - disk_reserve;
+        // A zero total means the size is unknown (e.g. partition not found)
+        .filter(|(_, total, committed, _)| *total > 0.0 && committed > total)
+        .map(|(name, total, committed, unit)| {
+            format!(
+                "{} over by {}{} ({}{} committed of {}{})",
+                name,
+                format_decimal(committed - total),
+                unit,
+                format_decimal(*committed),
+                unit,
+                format_decimal(*total),
+                unit
+            )
+        })
+        .collect();
 
-    // System overview table
-    println!(
-        "{}",
-        "╔═══════════════════════════════════════╗".bright_cyan()
-    );
-    println!(
-        "{}",
-        "║      SYSTEM RESOURCE OVERVIEW         ║"
-            .bright_cyan()
-            .bold()
-    );
-    println!(
-        "{}",
-        "╚═══════════════════════════════════════╝".bright_cyan()
-    );
-    println!();
+    if overages.is_empty() {
+        None
+    } else {
+        Some(format!("Oversubscribed: {}", overages.join(", ")))
+    }
+}
 
-    let mut overview_table = Table::new();
-    overview_table
+/// Print how the available figure for `request --explain` was reached, next to
+/// what was requested
+pub fn print_availability_breakdown(breakdown: &Availability, requested: (u32, f64, u32)) {
+    let mut table = Table::new();
+    table
         .load_preset(UTF8_FULL)
         .apply_modifier(UTF8_ROUND_CORNERS)
         .set_header(vec![
-            Cell::new("Metric").fg(Color::Cyan),
+            Cell::new("").fg(Color::Cyan),
             Cell::new("CPUs").fg(Color::Cyan),
             Cell::new("RAM (GB)").fg(Color::Cyan),
             Cell::new("Disk (GB)").fg(Color::Cyan),
         ]);
 
+    let row = |label: String, values: (f64, f64, f64), color: Color| {
+        vec![
+            Cell::new(label).fg(color),
+            Cell::new(format_decimal(values.0)).fg(color),
+            Cell::new(format_decimal(values.1)).fg(color),
+            Cell::new(format_decimal(values.2)).fg(color),
+        ]
+    };
+
+    table.add_row(row("Total".to_string(), breakdown.totals, Color::White));
+    table.add_row(row(
+        "- Reserved (System)".to_string(),
+        breakdown.reserves,
+        Color::Magenta,
+    ));
+    if breakdown.holds != (0.0, 0.0) {
+        table.add_row(row(
+            "- Scheduled holds".to_string(),
+            (breakdown.holds.0, breakdown.holds.1, 0.0),
+            Color::Magenta,
+        ));
+    }
+    for (uid, held) in &breakdown.other_users {
+        let username = get_username_from_uid(uid).unwrap_or_else(|| format!("({})", uid));
+        table.add_row(row(
+            format!("- {} ({})", username, uid),
+            *held,
+            Color::Yellow,
+        ));
+    }
+    table.add_row(row(
+        "= Available".to_string(),
+        breakdown.before_ceiling(),
+        Color::Green,
+    ));
+    if let Some(ceiling) = breakdown.ceiling {
+        table.add_row(row("Fair share cap".to_string(), ceiling, Color::Blue));
+    }
+    table.add_row(row(
+        "Requested".to_string(),
+        (requested.0 as f64, requested.1, requested.2 as f64),
+        Color::Cyan,
+    ));
+
+    println!("{}", "Availability Breakdown:".bright_cyan().bold());
+    println!();
+    println!("{}", table);
+    println!();
+}
+
+/// One entry of `fairshare allocations --json`. Field names are a stable interface
+/// for monitoring; add fields rather than renaming them.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AllocationRecord {
+    pub uid: u32,
+    /// None when the UID has no passwd entry
+    pub username: Option<String>,
+    /// CPU limit in cores (0 when unlimited)
+    pub cpu_cores: f64,
+    /// Memory limit in bytes (0 when unlimited)
+    pub mem_bytes: u64,
+}
+
+/// `allocations` as a JSON array of `AllocationRecord`s, in UID order
+pub fn allocations_json<F>(allocations: &[UserAlloc], username: F) -> io::Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut records: Vec<AllocationRecord> = allocations
+        .iter()
+        .filter_map(|alloc| {
+            Some(AllocationRecord {
+                uid: alloc.uid.parse().ok()?,
+                username: username(&alloc.uid),
+                cpu_cores: alloc.cpu_quota / 100.0,
+                mem_bytes: alloc.mem_bytes,
+            })
+        })
+        .collect();
+    records.sort_by_key(|r| r.uid);
+
+    serde_json::to_string_pretty(&records).map_err(io::Error::other)
+}
+
+/// Order allocations for the status table: cpu and mem largest first, uid and
+/// user ascending. The sort is stable, so ties keep their original order.
+pub fn sort_allocations<F>(allocations: &mut [UserAlloc], key: StatusSort, username: F)
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let numeric_uid = |a: &UserAlloc| a.uid.parse::<u64>().unwrap_or(u64::MAX);
+
+    match key {
+        StatusSort::Cpu => allocations.sort_by(|a, b| b.cpu_quota.total_cmp(&a.cpu_quota)),
+        StatusSort::Mem => allocations.sort_by_key(|a| std::cmp::Reverse(a.mem_bytes)),
+        StatusSort::Uid => allocations.sort_by_key(numeric_uid),
+        // Users without a passwd entry sort after named ones
+        StatusSort::User => allocations.sort_by_cached_key(|a| match username(&a.uid) {
+            Some(name) => (0, name),
+            None => (1, a.uid.clone()),
+        }),
+    }
+}
+
+pub fn print_status(
+    totals: &SystemTotals,
+    allocations: &[UserAlloc],
+    limits: &AllocationLimits,
+    sort: StatusSort,
+    style: TableStyle,
+) {
+    print_status_overview(totals, allocations, limits, style);
+
+    let mut allocations = allocations.to_vec();
+    sort_allocations(&mut allocations, sort, get_username_from_uid);
+    print_per_user_table(allocations, style);
+}
+
+/// `fairshare allocations` without --json: the per-user table from `status`, in UID order
+pub fn print_allocations(mut allocations: Vec<UserAlloc>) {
+    sort_allocations(&mut allocations, StatusSort::Uid, get_username_from_uid);
+    print_per_user_table(allocations, TableStyle::Full);
+}
+
+/// An empty status table with the borders `style` asks for
+fn new_table(style: TableStyle) -> Table {
+    let mut table = Table::new();
+    match style {
+        TableStyle::Full => table
+            .load_preset(UTF8_FULL)
+            .apply_modifier(UTF8_ROUND_CORNERS),
+        TableStyle::Ascii => table.load_preset(ASCII_FULL),
+        TableStyle::Minimal => table.load_preset(ASCII_NO_BORDERS),
+        TableStyle::None => table.load_preset(NOTHING),
+    };
+    table
+}
+
+/// Section title above a status table: boxed for `full`, plain text otherwise
+fn status_banner(title: &str, style: TableStyle) -> String {
+    match style {
+        TableStyle::Full => format!(
+            "{}\n{}\n{}",
+            "╔═══════════════════════════════════════╗".bright_cyan(),
+            format!("║{:^39}║", title).bright_cyan().bold(),
+            "╚═══════════════════════════════════════╝".bright_cyan()
+        ),
+        _ => title.bright_cyan().bold().to_string(),
+    }
+}
+
+/// The reserved, allocated and available rows of the status overview
+struct OverviewFigures {
+    reserved: (f64, f64, f64),
+    allocated: (f64, f64, f64),
+    available: (f64, f64, f64),
+    rounding: Rounding,
+}
+
+/// Overview figures from the same `compute_availability` that `check_request`
+/// judges requests with, so "Available" is what a new user could be granted
+/// before any fair share cap. Active scheduled holds count as reserved, and
+/// login users without a live slice count as allocated their default.
+fn overview_figures(
+    totals: &SystemTotals,
+    allocations: &[UserAlloc],
+    limits: &AllocationLimits,
+) -> OverviewFigures {
+    let availability = compute_availability(totals, allocations, limits, None);
+    let (cpu_reserve, mem_reserve, disk_reserve) = availability.reserves;
+    let (cpu_held, mem_held) = availability.holds;
+
+    OverviewFigures {
+        reserved: (cpu_reserve + cpu_held, mem_reserve + mem_held, disk_reserve),
+        allocated: availability.held(),
+        available: availability.before_ceiling(),
+        rounding: availability.rounding,
+    }
+}
+
+/// Totals, reserves, allocated and available, plus the oversubscription warning
+pub fn print_status_overview(
+    totals: &SystemTotals,
+    allocations: &[UserAlloc],
+    limits: &AllocationLimits,
+    style: TableStyle,
+) {
+    let figures = overview_figures(totals, allocations, limits);
+    let (cpu_reserve, mem_reserve, disk_reserve) = figures.reserved;
+    let (used_cpu, used_mem, used_disk) = figures.allocated;
+    let (available_cpu, available_mem, available_disk) = figures.available;
+
+    // System overview table
+    println!("{}", status_banner("SYSTEM RESOURCE OVERVIEW", style));
+    println!();
+
+    let mut overview_table = new_table(style);
+    overview_table.set_header(vec![
+        Cell::new("Metric").fg(Color::Cyan),
+        Cell::new("CPUs").fg(Color::Cyan),
+        Cell::new("RAM (GB)").fg(Color::Cyan),
+        Cell::new("Disk (GB)").fg(Color::Cyan),
+    ]);
+
     overview_table.add_row(vec![
         Cell::new("Total").fg(Color::White),
         Cell::new(format!("{}", totals.total_cpu)).fg(Color::White),
-        Cell::new(format!("{:.2}", totals.total_mem_gb)).fg(Color::White),
-        Cell::new(format!("{:.2}", totals.total_disk_gb)).fg(Color::White),
+        Cell::new(format_decimal(totals.total_mem_gb)).fg(Color::White),
+        Cell::new(format_decimal(totals.total_disk_gb)).fg(Color::White),
     ]);
 
     // Show system reserves if configured
     if cpu_reserve > 0.0 || mem_reserve > 0.0 || disk_reserve > 0.0 {
         let cpu_reserve_str = if cpu_reserve > 0.0 {
-            format!("{:.2}", cpu_reserve)
+            format_decimal(cpu_reserve)
         } else {
             "-".to_string()
         };
         let mem_reserve_str = if mem_reserve > 0.0 {
-            format!("{:.2}", mem_reserve)
+            format_decimal(mem_reserve)
         } else {
             "-".to_string()
         };
         let disk_reserve_str = if disk_reserve > 0.0 {
-            format!("{:.2}", disk_reserve)
+            format_decimal(disk_reserve)
         } else {
             "-".to_string()
         };
-        overview_table.add_row(vec![
-            Cell::new("Reserved (System)").fg(Color::Magenta),
-            Cell::new(cpu_reserve_str).fg(Color::Magenta),
-            Cell::new(mem_reserve_str).fg(Color::Magenta),
-            Cell::new(disk_reserve_str).fg(Color::Magenta),
-        ]);
+        overview_table.add_row(vec![
+            Cell::new("Reserved (System)").fg(Color::Magenta),
+            Cell::new(cpu_reserve_str).fg(Color::Magenta),
+            Cell::new(mem_reserve_str).fg(Color::Magenta),
+            Cell::new(disk_reserve_str).fg(Color::Magenta),
+        ]);
+    }
+
+    overview_table.add_row(vec![
+        Cell::new("Allocated").fg(Color::Yellow),
+        Cell::new(format_decimal(used_cpu)).fg(Color::Yellow),
+        Cell::new(format_decimal(used_mem)).fg(Color::Yellow),
+        Cell::new(format_decimal(used_disk)).fg(Color::Yellow),
+    ]);
+
+    // Negative availability means allocations plus reserves exceed the machine
+    let rounding = figures.rounding;
+    let available_cell = |v: f64| {
+        if v < 0.0 {
+            Cell::new(format_decimal(v)).fg(Color::Red)
+        } else {
+            Cell::new(rounding.whole(v)).fg(Color::Green)
+        }
+    };
+    overview_table.add_row(vec![
+        Cell::new("Available").fg(Color::Green),
+        available_cell(available_cpu),
+        available_cell(available_mem),
+        available_cell(available_disk),
+    ]);
+
+    println!("{}", overview_table);
+    println!();
+
+    if let Some(warning) = oversubscription_warning(
+        totals,
+        (used_cpu, used_mem, used_disk),
+        (cpu_reserve, mem_reserve, disk_reserve),
+    ) {
+        println!("{} {}", "⚠".red().bold(), warning.red().bold());
+        println!();
+    }
+}
+
+fn print_per_user_table(allocations: Vec<UserAlloc>, style: TableStyle) {
+    // Per-user allocations table
+    if !allocations.is_empty() {
+        println!("{}", "Per-User Allocations:".bright_cyan().bold());
+        println!();
+
+        let mut user_table = new_table(style);
+        user_table.set_header(vec![
+            Cell::new("Username").fg(Color::Cyan),
+            Cell::new("UID").fg(Color::Cyan),
+            Cell::new("CPU Quota").fg(Color::Cyan),
+            Cell::new("CPUs").fg(Color::Cyan),
+            Cell::new("RAM (GB)").fg(Color::Cyan),
+            Cell::new("Disk (GB)").fg(Color::Cyan),
+            Cell::new("Scope").fg(Color::Cyan),
+        ]);
+
+        for a in allocations {
+            let lookup = a
+                .uid
+                .parse()
+                .map_or(UsernameLookup::LookupError, lookup_username);
+            let (username, username_color) = username_cell(&a.uid, &lookup);
+
+            // Check if user has no custom allocations (both CPU and Memory are 0)
+            let has_no_allocation = a.cpu_quota == 0.0 && a.mem_bytes == 0 && a.disk_bytes == 0;
+
+            if has_no_allocation {
+                // Display "Not Set" for users without custom resource limits
+                user_table.add_row(vec![
+                    Cell::new(username).fg(username_color),
+                    Cell::new(&a.uid).fg(Color::White),
+                    Cell::new("Not Set").fg(Color::DarkGrey),
+                    Cell::new("Not Set").fg(Color::DarkGrey),
+                    Cell::new("Not Set").fg(Color::DarkGrey),
+                    Cell::new("Not Set").fg(Color::DarkGrey),
+                    Cell::new("-").fg(Color::DarkGrey),
+                ]);
+            } else {
+                // Display actual values for users with custom allocations
+                let cpu_cores = a.cpu_quota / 100.0;
+                let mem_gb = a.mem_bytes as f64 / 1_000_000_000.0;
+                let disk_gb = a.disk_bytes as f64 / 1_000_000_000.0;
+                user_table.add_row(vec![
+                    Cell::new(username).fg(username_color),
+                    Cell::new(&a.uid).fg(Color::White),
+                    Cell::new(format!("{}%", format_decimal(a.cpu_quota))).fg(Color::Yellow),
+                    Cell::new(format_decimal(cpu_cores)).fg(Color::Yellow),
+                    Cell::new(format_decimal(mem_gb)).fg(Color::Yellow),
+                    Cell::new(format_decimal(disk_gb)).fg(Color::Yellow),
+                    Cell::new(get_allocation_scope(&a.uid).unwrap_or("Default")).fg(Color::White),
+                ]);
+            }
+        }
+
+        println!("{}", user_table);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_username_found_is_cached() {
+        let mut cache = BTreeMap::new();
+        let lookup = resolve_username(1000, |_| Ok(Some("alice".to_string())), &mut cache);
+
+        assert_eq!(lookup, UsernameLookup::Found("alice".to_string()));
+        assert_eq!(cache.get(&1000).map(String::as_str), Some("alice"));
+        assert_eq!(
+            username_cell("1000", &lookup),
+            ("alice".to_string(), Color::White)
+        );
+    }
+
+    #[test]
+    fn test_resolve_username_nss_error() {
+        let nss_down = |_| Err(io::Error::from_raw_os_error(libc::EIO));
+
+        // Nothing cached: shown as a lookup failure, not as a deleted user
+        let mut cache = BTreeMap::new();
+        let lookup = resolve_username(1000, nss_down, &mut cache);
+        assert_eq!(lookup, UsernameLookup::LookupError);
+        assert_eq!(
+            username_cell("1000", &lookup),
+            ("(1000) lookup failed".to_string(), Color::DarkGrey)
+        );
+
+        // Resolved earlier: the cached name is used instead of flapping
+        cache.insert(1000, "alice".to_string());
+        assert_eq!(
+            resolve_username(1000, nss_down, &mut cache),
+            UsernameLookup::Found("alice".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve_username_deleted_user() {
+        let mut cache = BTreeMap::from([(1000, "alice".to_string())]);
+        let lookup = resolve_username(1000, |_| Ok(None), &mut cache);
+
+        assert_eq!(lookup, UsernameLookup::NoSuchUser);
+        assert!(cache.is_empty());
+        assert_eq!(
+            username_cell("1000", &lookup),
+            ("(1000) deleted user?".to_string(), Color::Red)
+        );
+    }
+
+    #[test]
+    fn test_getpwuid_name() {
+        assert_eq!(getpwuid_name(0).unwrap().as_deref(), Some("root"));
+        // Well above any allocated UID
+        assert_eq!(getpwuid_name(4_000_000_000).unwrap(), None);
+    }
+
+    #[test]
+    fn test_allocations_json_array() {
+        let allocations = vec![
+            UserAlloc {
+                uid: "1001".to_string(),
+                cpu_quota: 250.0,
+                mem_bytes: 8_000_000_000,
+                disk_bytes: 0,
+            },
+            UserAlloc {
+                uid: "1000".to_string(),
+                cpu_quota: 0.0,
+                mem_bytes: 0,
+                disk_bytes: 0,
+            },
+        ];
+        let json = allocations_json(&allocations, |uid| {
+            (uid == "1001").then(|| "alice".to_string())
+        })
+        .unwrap();
+
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let entries = parsed.as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["uid"], 1000);
+        assert!(entries[0]["username"].is_null());
+        assert_eq!(entries[1]["uid"], 1001);
+        assert_eq!(entries[1]["username"], "alice");
+        assert_eq!(entries[1]["cpu_cores"], 2.5);
+        assert_eq!(entries[1]["mem_bytes"], 8_000_000_000u64);
+
+        assert_eq!(allocations_json(&[], |_| None).unwrap(), "[]");
+    }
+
+    fn has_box_drawing(s: &str) -> bool {
+        s.chars().any(|c| ('\u{2500}'..='\u{257F}').contains(&c))
+    }
+
+    #[test]
+    fn test_table_style_ascii_has_no_box_drawing() {
+        for style in [TableStyle::Ascii, TableStyle::Minimal, TableStyle::None] {
+            let mut table = new_table(style);
+            table.set_header(vec!["Metric", "CPUs"]);
+            table.add_row(vec!["Total", "16"]);
+
+            assert!(!has_box_drawing(&table.to_string()), "{:?}", style);
+            assert!(!has_box_drawing(&status_banner(
+                "SYSTEM RESOURCE OVERVIEW",
+                style
+            )));
+        }
+
+        let mut table = new_table(TableStyle::Full);
+        table.set_header(vec!["Metric"]);
+        assert!(has_box_drawing(&table.to_string()));
+        assert!(has_box_drawing(&status_banner(
+            "SYSTEM RESOURCE OVERVIEW",
+            TableStyle::Full
+        )));
+    }
+
+    #[test]
+    fn test_check_systemctl_failure_includes_stderr() {
+        use std::os::unix::process::ExitStatusExt;
+        let output = Output {
+            status: std::process::ExitStatus::from_raw(1 << 8),
+            stdout: vec![],
+            stderr: b"Failed to set unit properties: Access denied\n".to_vec(),
+        };
+
+        let err = check_systemctl(&["set-property", "user-1000.slice"], output).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "systemctl set-property user-1000.slice failed (exit code: Some(1)): \
+             Failed to set unit properties: Access denied"
+        );
+    }
+
+    #[test]
+    fn test_check_systemctl_success_passes_output_through() {
+        use std::os::unix::process::ExitStatusExt;
+        let output = Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: b"MemoryMax=infinity\n".to_vec(),
+            stderr: vec![],
+        };
+
+        let output = check_systemctl(&["show"], output).unwrap();
+        assert_eq!(output.stdout, b"MemoryMax=infinity\n");
+    }
+
+    fn run_wizard(
+        script: &str,
+        fits: impl FnMut(u32, u32) -> bool,
+    ) -> (io::Result<Option<(u32, u32)>>, String) {
+        let mut input = io::Cursor::new(script.as_bytes());
+        let mut output = Vec::new();
+        let result = request_wizard(&mut input, &mut output, (6, 12, 0), fits);
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_request_wizard_accepts_suggested_defaults() {
+        let (result, output) = run_wizard("\n\ny\n", |_, _| true);
+
+        assert_eq!(result.unwrap(), Some((6, 12)));
+        assert!(output.contains("Available: 6 CPU(s), 12G RAM"));
+        assert!(output.contains("CPUs [6]: "));
+        assert!(output.contains("Memory (GB) [12]: "));
+    }
+
+    #[test]
+    fn test_request_wizard_reprompts_on_invalid_input() {
+        let (result, output) = run_wizard("abc\n0\n4\n-1\n8\ny\n", |_, _| true);
+
+        assert_eq!(result.unwrap(), Some((4, 8)));
+        assert_eq!(output.matches("Enter a whole number").count(), 3);
+    }
+
+    #[test]
+    fn test_request_wizard_reprompts_when_request_does_not_fit() {
+        let mut checked = vec![];
+        let (result, output) = run_wizard("10\n4\n2\n4\nyes\n", |cpu, mem| {
+            checked.push((cpu, mem));
+            cpu <= 6
+        });
+
+        assert_eq!(result.unwrap(), Some((2, 4)));
+        assert_eq!(checked, vec![(10, 4), (2, 4)]);
+        assert!(output.contains("10 CPU(s) and 4G RAM exceeds what is available"));
+    }
+
+    #[test]
+    fn test_request_wizard_declined_or_interrupted() {
+        let (result, _) = run_wizard("2\n4\nn\n", |_, _| true);
+        assert_eq!(result.unwrap(), None);
+
+        let (result, _) = run_wizard("2\n", |_, _| true);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_format_decimal_ignores_lc_numeric() {
+        std::env::set_var("LC_NUMERIC", "de_DE.UTF-8");
+        assert_eq!(format_decimal(1234.5), "1234.50");
+        assert_eq!(format_decimal(0.125), "0.12");
+        assert_eq!(format_decimal(2.0 / 3.0), "0.67");
+        std::env::remove_var("LC_NUMERIC");
+    }
+
+    #[test]
+    fn test_parse_mem_gb_with_gigabytes() {
+        assert_eq!(parse_mem_gb("4G"), 4.0);
+        assert_eq!(parse_mem_gb("8g"), 8.0);
+        assert_eq!(parse_mem_gb("16G"), 16.0);
+    }
+
+    #[test]
+    fn test_parse_mem_gb_with_megabytes() {
+        assert_eq!(parse_mem_gb("1024M"), 1.0);
+        assert_eq!(parse_mem_gb("2048m"), 2.0);
+        assert_eq!(parse_mem_gb("512M"), 0.5);
+    }
+
+    #[test]
+    fn test_parse_mem_gb_plain_number() {
+        assert_eq!(parse_mem_gb("4"), 4.0);
+        assert_eq!(parse_mem_gb("8.5"), 8.5);
+    }
+
+    #[test]
+    fn test_parse_mem_gb_invalid() {
+        assert_eq!(parse_mem_gb("invalid"), 0.0);
+        assert_eq!(parse_mem_gb(""), 0.0);
+    }
+
+    #[test]
+    fn test_all_available_request() {
+        assert_eq!(all_available_request((4, 8, 0), (0, 0)), Ok((4, 8, 0)));
+        assert_eq!(
+            all_available_request((0, 0, 10), (0, 0)),
+            Err("No resources available to allocate.")
+        );
+    }
+
+    #[test]
+    fn test_all_request_without_existing_allocation() {
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![alloc("1000", 400.0, 8, 0)];
+        let limits = AllocationLimits::default();
+
+        let available = calculate_available_resources(&totals, &allocations, Some("1001"), &limits);
+        let held = current_holding(&allocations, Some("1001"));
+
+        assert_eq!(held, (0, 0));
+        assert_eq!(all_available_request(available, held), Ok((12, 24, 100)));
+        assert_eq!(all_request_message(held, (12, 24)), None);
+    }
+
+    #[test]
+    fn test_all_request_reclaims_existing_allocation() {
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![alloc("1000", 400.0, 8, 0), alloc("1001", 400.0, 8, 0)];
+        let limits = AllocationLimits::default();
+
+        // 1001's own 4 CPUs / 8G are counted back into what it can take
+        let available = calculate_available_resources(&totals, &allocations, Some("1001"), &limits);
+        let held = current_holding(&allocations, Some("1001"));
+
+        assert_eq!(held, (4, 8));
+        assert_eq!(all_available_request(available, held), Ok((12, 24, 100)));
+        assert_eq!(
+            all_request_message(held, (12, 24)).unwrap(),
+            "Reclaimed your previous 4 CPU(s) and 8G RAM and allocated a total of 12 CPU(s) and 24G RAM."
+        );
+    }
+
+    #[test]
+    fn test_all_request_never_below_current_holding() {
+        // Others oversubscribed the machine: only 2 CPUs / 3G show as available
+        assert_eq!(all_available_request((2, 3, 0), (4, 8)), Ok((4, 8, 0)));
+        assert_eq!(all_available_request((0, 0, 0), (4, 8)), Ok((4, 8, 0)));
+    }
+
+    #[test]
+    fn test_check_request_keeping_skips_only_held_capacity() {
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        // 1000 oversubscribed the CPUs: 1001's 4 CPUs show only 2 as available
+        let allocations = vec![alloc("1000", 1400.0, 8, 0), alloc("1001", 400.0, 8, 0)];
+        let check = |cpu, mem: &str, limits: &AllocationLimits| {
+            check_request_keeping(
+                &totals,
+                &allocations,
+                cpu,
+                mem,
+                0,
+                Some("1001"),
+                limits,
+                (4, 8),
+            )
+        };
+        let limits = AllocationLimits::default();
+
+        assert_eq!(
+            check_request(&totals, &allocations, 4, "8", 0, Some("1001"), &limits),
+            Err(None)
+        );
+        assert_eq!(check(4, "8", &limits), Ok(()));
+        // Memory beyond what is held must still be free: 24G is, 25G isn't
+        assert_eq!(check(4, "24", &limits), Ok(()));
+        assert_eq!(check(4, "25", &limits), Err(None));
+
+        // The ratio and fair share checks apply to the held amount too
+        let ratio = AllocationLimits {
+            max_mem_per_cpu_gb: Some(1),
+            ..AllocationLimits::default()
+        };
+        assert!(check(4, "8", &ratio).is_err_and(|r| r.is_some_and(|r| r.contains("1G-per-CPU"))));
+        let fair_share = AllocationLimits {
+            fair_share_users: Some(8),
+            ..AllocationLimits::default()
+        };
+        assert!(check(4, "8", &fair_share)
+            .is_err_and(|r| r.is_some_and(|r| r.contains("8 active user(s)"))));
+    }
+
+    #[test]
+    fn test_all_available_request_cpu_exhausted() {
+        assert_eq!(
+            all_available_request((0, 4, 0), (0, 0)),
+            Err("No CPU available to allocate.")
+        );
+    }
+
+    #[test]
+    fn test_all_available_request_memory_exhausted() {
+        assert_eq!(
+            all_available_request((2, 0, 0), (0, 0)),
+            Err("No memory available to allocate.")
+        );
+    }
+
+    #[test]
+    fn test_best_effort_grant_full_fit() {
+        assert_eq!(
+            best_effort_grant((8, 16.0, 0), (12, 32, 50), 1, 1),
+            Ok((8, 16.0, 0))
+        );
+    }
+
+    #[test]
+    fn test_best_effort_grant_partial_fit() {
+        assert_eq!(
+            best_effort_grant((8, 16.0, 20), (3, 10, 5), 2, 4),
+            Ok((3, 10.0, 5))
+        );
+    }
+
+    #[test]
+    fn test_best_effort_grant_below_floor() {
+        assert!(best_effort_grant((8, 16.0, 0), (3, 10, 0), 4, 1)
+            .unwrap_err()
+            .contains("below the minimum of 4"));
+        assert!(best_effort_grant((8, 16.0, 0), (3, 10, 0), 1, 12)
+            .unwrap_err()
+            .contains("below the minimum of 12G"));
+    }
+
+    #[test]
+    fn test_parse_session_uid() {
+        let session =
+            "# This is private data. Do not parse.\nUID=1000\nUSER=alice\nACTIVE=1\nSTATE=active\n";
+        assert_eq!(parse_session_uid(session), Some(1000));
+
+        let closing = "UID=1001\nUSER=bob\nSTATE=closing\n";
+        assert_eq!(parse_session_uid(closing), None);
+
+        let system = "UID=120\nUSER=gdm\nSTATE=online\n";
+        assert_eq!(parse_session_uid(system), None);
+
+        assert_eq!(parse_session_uid("USER=nouid\n"), None);
+    }
+
+    #[test]
+    fn test_parse_slices_show_multiple_units() {
+        let output = "Id=user-0.slice\nMemoryMax=infinity\nCPUQuotaPerSecUSec=infinity\n\n\
+                      Id=user-1000.slice\nMemoryMax=8000000000\nCPUQuotaPerSecUSec=4s\n\n\
+                      Id=user-1001.slice\nMemoryMax=infinity\nCPUQuotaPerSecUSec=infinity\n\n\
+                      Id=user.slice\nMemoryMax=infinity\nCPUQuotaPerSecUSec=infinity\n";
+
+        let allocs = parse_slices_show(output);
+        assert_eq!(allocs.len(), 2);
+        assert_eq!(allocs[0].uid, "1000");
+        assert_eq!(allocs[0].cpu_quota, 400.0);
+        assert_eq!(allocs[0].mem_bytes, 8_000_000_000);
+        assert_eq!(allocs[1].uid, "1001");
+        assert_eq!((allocs[1].cpu_quota, allocs[1].mem_bytes), (0.0, 0));
+    }
+
+    #[test]
+    fn test_allocation_scope_detection() {
+        let base =
+            std::env::temp_dir().join(format!("fairshare-scope-test-{}", std::process::id()));
+        let persistent = base.join("etc");
+        let runtime = base.join("run");
+        fs::create_dir_all(persistent.join("user-1000.slice.d")).unwrap();
+        fs::create_dir_all(persistent.join("user-1001.slice.d")).unwrap();
+        fs::create_dir_all(runtime.join("user-1001.slice.d")).unwrap();
+        fs::create_dir_all(runtime.join("user-1002.slice.d")).unwrap();
+
+        let scope_1000 = allocation_scope_in(&persistent, &runtime, "1000");
+        let scope_1001 = allocation_scope_in(&persistent, &runtime, "1001");
+        let scope_1002 = allocation_scope_in(&persistent, &runtime, "1002");
+        let scope_1003 = allocation_scope_in(&persistent, &runtime, "1003");
+        fs::remove_dir_all(&base).ok();
+
+        assert_eq!(scope_1000, Some("Persistent"));
+        // Runtime drop-ins override persistent ones
+        assert_eq!(scope_1001, Some("Runtime"));
+        assert_eq!(scope_1002, Some("Runtime"));
+        assert_eq!(scope_1003, None);
+    }
+
+    #[test]
+    fn test_wait_for_resources_fits_immediately() {
+        let mut sleeps = 0;
+        let result = wait_for_resources(
+            |_| Ok(()),
+            Some(Duration::from_secs(10)),
+            Duration::from_secs(5),
+            |_| sleeps += 1,
+        );
+        assert_eq!(result, Ok(true));
+        assert_eq!(sleeps, 0, "Should not sleep when the request already fits");
+    }
+
+    #[test]
+    fn test_wait_for_resources_fits_after_one_poll() {
+        let mut polls = 0;
+        let mut sleeps = 0;
+        let result = wait_for_resources(
+            |_| {
+                polls += 1;
+                if polls > 1 {
+                    Ok(())
+                } else {
+                    Err(None)
+                }
+            },
+            Some(Duration::from_secs(60)),
+            Duration::from_secs(5),
+            |_| sleeps += 1,
+        );
+        assert_eq!(result, Ok(true));
+        assert_eq!(polls, 2);
+        assert_eq!(sleeps, 1);
+    }
+
+    #[test]
+    fn test_wait_for_resources_times_out() {
+        let mut sleeps = 0;
+        let result = wait_for_resources(
+            |_| Err(None),
+            Some(Duration::from_secs(12)),
+            Duration::from_secs(5),
+            |_| sleeps += 1,
+        );
+        assert_eq!(result, Ok(false));
+        // Polls at 0s, 5s, 10s and 15s before giving up
+        assert_eq!(sleeps, 3);
+    }
+
+    #[test]
+    fn test_wait_for_resources_without_timeout_keeps_polling() {
+        let mut polls = 0;
+        let result = wait_for_resources(
+            |_| {
+                polls += 1;
+                if polls == 50 {
+                    Ok(())
+                } else {
+                    Err(None)
+                }
+            },
+            None,
+            Duration::from_secs(5),
+            |_| {},
+        );
+        assert_eq!(result, Ok(true));
+        assert_eq!(polls, 50);
+    }
+
+    #[test]
+    fn test_wait_for_resources_stops_on_policy_refusal() {
+        let mut polls = 0;
+        let mut sleeps = 0;
+        let result = wait_for_resources(
+            |_| {
+                polls += 1;
+                if polls == 1 {
+                    Err(None)
+                } else {
+                    Err(Some("over the fair share".to_string()))
+                }
+            },
+            None,
+            Duration::from_secs(5),
+            |_| sleeps += 1,
+        );
+        assert_eq!(result, Err("over the fair share".to_string()));
+        assert_eq!(sleeps, 1, "A policy refusal isn't waited on");
+    }
+
+    fn eight_core_16g() -> SystemTotals {
+        SystemTotals {
+            total_mem_gb: 16.0,
+            total_cpu: 8,
+            total_disk_gb: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_profile_defaults_workstation() {
+        assert_eq!(
+            profile_defaults(SetupProfile::Workstation, &eight_core_16g()),
+            (4, 8, 1, 2)
+        );
+    }
+
+    #[test]
+    fn test_profile_defaults_hpc() {
+        // Half a CPU rounds down to zero and is clamped to 1
+        assert_eq!(
+            profile_defaults(SetupProfile::Hpc, &eight_core_16g()),
+            (1, 1, 2, 4)
+        );
+    }
+
+    #[test]
+    fn test_profile_defaults_shared() {
+        assert_eq!(
+            profile_defaults(SetupProfile::Shared, &eight_core_16g()),
+            (2, 4, 1, 2)
+        );
+    }
+
+    #[test]
+    fn test_profile_defaults_tiny_machine_never_zero() {
+        let totals = SystemTotals {
+            total_mem_gb: 1.0,
+            total_cpu: 1,
+            total_disk_gb: 0.0,
+        };
+        assert_eq!(profile_defaults(SetupProfile::Hpc, &totals), (1, 1, 1, 1));
+    }
+
+    #[test]
+    fn test_output_with_timeout_kills_slow_command() {
+        let start = Instant::now();
+        let err = output_with_timeout(Command::new("sleep").arg("5"), Duration::from_millis(100))
+            .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(err.to_string().contains("did not finish within"));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_output_with_timeout_returns_output() {
+        let output = output_with_timeout(
+            Command::new("echo").arg("user-1000.slice"),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "user-1000.slice\n");
+    }
+
+    fn sort_uids(key: StatusSort) -> Vec<String> {
+        // 1002 and 1003 tie on CPU, 1000 and 1003 tie on memory
+        let mut allocations = vec![
+            alloc("1003", 200.0, 8, 0),
+            alloc("1000", 100.0, 8, 0),
+            alloc("1010", 400.0, 2, 0),
+            alloc("1002", 200.0, 16, 0),
+        ];
+        let names = |uid: &str| match uid {
+            "1000" => Some("dave".to_string()),
+            "1002" => Some("alice".to_string()),
+            "1003" => Some("carol".to_string()),
+            _ => None,
+        };
+
+        sort_allocations(&mut allocations, key, names);
+        allocations.into_iter().map(|a| a.uid).collect()
     }
 
-    overview_table.add_row(vec![
-        Cell::new("Allocated").fg(Color::Yellow),
-        Cell::new(format!("{:.2}", used_cpu)).fg(Color::Yellow),
-        Cell::new(format!("{:.2}", used_mem)).fg(Color::Yellow),
-        Cell::new(format!("{:.2}", used_disk)).fg(Color::Yellow),
-    ]);
+    #[test]
+    fn test_sort_allocations_by_cpu_descending_stable() {
+        assert_eq!(sort_uids(StatusSort::Cpu), ["1010", "1003", "1002", "1000"]);
+    }
 
-    overview_table.add_row(vec![
-        Cell::new("Available").fg(Color::Green),
-        Cell::new(format!("{:.2}", available_cpu)).fg(Color::Green),
-        Cell::new(format!("{:.2}", available_mem)).fg(Color::Green),
-        Cell::new(format!("{:.2}", available_disk)).fg(Color::Green),
-    ]);
+    #[test]
+    fn test_sort_allocations_by_mem_descending_stable() {
+        assert_eq!(sort_uids(StatusSort::Mem), ["1002", "1003", "1000", "1010"]);
+    }
 
-    println!("{}", overview_table);
-    println!();
+    #[test]
+    fn test_sort_allocations_by_uid_numeric() {
+        let mut allocations = vec![alloc("1000", 0.0, 0, 0), alloc("999", 0.0, 0, 0)];
+        sort_allocations(&mut allocations, StatusSort::Uid, |_| None);
+        assert_eq!(allocations[0].uid, "999");
 
-    // Per-user allocations table
-    if !allocations.is_empty() {
-        println!("{}", "Per-User Allocations:".bright_cyan().bold());
-        println!();
+        assert_eq!(sort_uids(StatusSort::Uid), ["1000", "1002", "1003", "1010"]);
+    }
 
-        let mut user_table = Table::new();
-        user_table
-            .load_preset(UTF8_FULL)
-            .apply_modifier(UTF8_ROUND_CORNERS)
-            .set_header(vec![
-                Cell::new("Username").fg(Color::Cyan),
-                Cell::new("UID").fg(Color::Cyan),
-                Cell::new("CPU Quota").fg(Color::Cyan),
-                Cell::new("CPUs").fg(Color::Cyan),
-                Cell::new("RAM (GB)").fg(Color::Cyan),
-                Cell::new("Disk (GB)").fg(Color::Cyan),
-            ]);
+    #[test]
+    fn test_sort_allocations_by_user_unknown_last() {
+        assert_eq!(
+            sort_uids(StatusSort::User),
+            ["1002", "1003", "1000", "1010"]
+        );
+    }
 
-        for a in allocations {
-            let username = get_username_from_uid(&a.uid).unwrap_or_else(|| format!("({})", a.uid));
+    #[test]
+    fn test_active_hold_totals() {
+        let hold = |cpu, mem, from: &str, until: &str| ScheduledHold {
+            cpu,
+            mem,
+            from: from.to_string(),
+            until: until.to_string(),
+        };
+        let holds = vec![hold(16, 64, "02:00", "06:00"), hold(4, 8, "22:00", "03:00")];
 
-            // Check if user has no custom allocations (both CPU and Memory are 0)
-            let has_no_allocation = a.cpu_quota == 0.0 && a.mem_bytes == 0 && a.disk_bytes == 0;
+        assert_eq!(active_hold_totals(&holds, 12 * 60), (0, 0));
+        assert_eq!(active_hold_totals(&holds, 23 * 60), (4, 8));
+        assert_eq!(active_hold_totals(&holds, 150), (20, 72));
+    }
 
-            if has_no_allocation {
-                // Display "Not Set" for users without custom resource limits
-                user_table.add_row(vec![
-                    Cell::new(username).fg(Color::White),
-                    Cell::new(&a.uid).fg(Color::White),
-                    Cell::new("Not Set").fg(Color::DarkGrey),
-                    Cell::new("Not Set").fg(Color::DarkGrey),
-                    Cell::new("Not Set").fg(Color::DarkGrey),
-                    Cell::new("Not Set").fg(Color::DarkGrey),
-                ]);
-            } else {
-                // Display actual values for users with custom allocations
-                let cpu_cores = a.cpu_quota / 100.0;
-                let mem_gb = a.mem_bytes as f64 / 1_000_000_000.0;
-                let disk_gb = a.disk_bytes as f64 / 1_000_000_000.0;
-                user_table.add_row(vec![
-                    Cell::new(username).fg(Color::White),
-                    Cell::new(&a.uid).fg(Color::White),
-                    Cell::new(format!("{:.1}%", a.cpu_quota)).fg(Color::Yellow),
-                    Cell::new(format!("{:.2}", cpu_cores)).fg(Color::Yellow),
-                    Cell::new(format!("{:.2}", mem_gb)).fg(Color::Yellow),
-                    Cell::new(format!("{:.2}", disk_gb)).fg(Color::Yellow),
-                ]);
-            }
-        }
+    #[test]
+    fn test_percent_of_totals() {
+        assert_eq!(percent_of_totals(&eight_core_16g(), 25, 50), (2, 8));
+        assert_eq!(percent_of_totals(&eight_core_16g(), 100, 100), (8, 16));
+    }
 
-        println!("{}", user_table);
+    #[test]
+    fn test_percent_of_totals_rounds_down_but_never_zero() {
+        // 30% of 8 CPUs = 2.4, 33% of 16G = 5.28
+        assert_eq!(percent_of_totals(&eight_core_16g(), 30, 33), (2, 5));
+        // 10% of 8 CPUs = 0.8 and 5% of 16G = 0.8 are clamped to 1
+        assert_eq!(percent_of_totals(&eight_core_16g(), 10, 5), (1, 1));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn alloc(uid: &str, cpu_quota: f64, mem_gb: u64, disk_gb: u64) -> UserAlloc {
+        UserAlloc {
+            uid: uid.to_string(),
+            cpu_quota,
+            mem_bytes: mem_gb * 1_000_000_000,
+            disk_bytes: disk_gb * 1_000_000_000,
+        }
+    }
 
     #[test]
-    fn test_parse_mem_gb_with_gigabytes() {
-        assert_eq!(parse_mem_gb("4G"), 4.0);
-        assert_eq!(parse_mem_gb("8g"), 8.0);
-        assert_eq!(parse_mem_gb("16G"), 16.0);
+    fn test_has_custom_allocation_no_slice() {
+        assert!(!has_custom_allocation(None, Some((1, 2, 0))));
+        assert!(!has_custom_allocation(None, None));
     }
 
     #[test]
-    fn test_parse_mem_gb_with_megabytes() {
-        assert_eq!(parse_mem_gb("1024M"), 1.0);
-        assert_eq!(parse_mem_gb("2048m"), 2.0);
-        assert_eq!(parse_mem_gb("512M"), 0.5);
+    fn test_has_custom_allocation_matches_default() {
+        let a = alloc("1000", 100.0, 2, 0);
+        assert!(!has_custom_allocation(Some(&a), Some((1, 2, 0))));
+
+        // Default disk quota applied at login is not a custom allocation either
+        let a = alloc("1000", 100.0, 2, 10);
+        assert!(!has_custom_allocation(Some(&a), Some((1, 2, 10))));
     }
 
     #[test]
-    fn test_parse_mem_gb_plain_number() {
-        assert_eq!(parse_mem_gb("4"), 4.0);
-        assert_eq!(parse_mem_gb("8.5"), 8.5);
+    fn test_has_custom_allocation_differs_from_default() {
+        assert!(has_custom_allocation(
+            Some(&alloc("1000", 400.0, 2, 0)),
+            Some((1, 2, 0))
+        ));
+        assert!(has_custom_allocation(
+            Some(&alloc("1000", 100.0, 8, 0)),
+            Some((1, 2, 0))
+        ));
+        assert!(has_custom_allocation(
+            Some(&alloc("1000", 100.0, 2, 50)),
+            Some((1, 2, 0))
+        ));
     }
 
     #[test]
-    fn test_parse_mem_gb_invalid() {
-        assert_eq!(parse_mem_gb("invalid"), 0.0);
-        assert_eq!(parse_mem_gb(""), 0.0);
+    fn test_has_custom_allocation_without_policy() {
+        assert!(!has_custom_allocation(
+            Some(&alloc("1000", 0.0, 0, 0)),
+            None
+        ));
+        assert!(has_custom_allocation(
+            Some(&alloc("1000", 200.0, 4, 0)),
+            None
+        ));
     }
 
     #[test]
-    fn test_check_request_sufficient_resources() {
+    fn test_simulate_allocations_reduces_availability_exactly() {
         let totals = SystemTotals {
-            total_mem_gb: 16.0,
-            total_cpu: 8,
+            total_mem_gb: 256.0,
+            total_cpu: 64,
             total_disk_gb: 100.0,
         };
-        let allocations = vec![UserAlloc {
-            uid: "1000".to_string(),
-            cpu_quota: 200.0,         // 2 CPUs
-            mem_bytes: 4_000_000_000, // 4 GB
-            disk_bytes: 0,
-        }];
+        let allocations = vec![alloc("1000", 400.0, 8, 0)];
 
-        // Request 2 CPUs and 4 GB - should be allowed
-        assert!(check_request(&totals, &allocations, 2, "4", 0, None));
+        let before = calculate_available_resources(
+            &totals,
+            &allocations,
+            None,
+            &AllocationLimits::default(),
+        );
+        let simulated = simulate_allocations(&allocations, &[(1001, 8, 16), (1002, 16, 32)]);
+        let after =
+            calculate_available_resources(&totals, &simulated, None, &AllocationLimits::default());
+
+        assert_eq!(simulated.len(), 3);
+        assert_eq!(before.0 - after.0, 24);
+        assert_eq!(before.1 - after.1, 48);
+        assert_eq!(before.2, after.2);
+    }
+
+    #[test]
+    fn test_simulate_allocations_replaces_existing_allocation() {
+        let allocations = vec![alloc("1000", 400.0, 8, 5)];
+        let simulated = simulate_allocations(&allocations, &[(1000, 2, 4)]);
+
+        assert_eq!(simulated.len(), 1);
+        assert_eq!(simulated[0].cpu_quota, 200.0);
+        assert_eq!(simulated[0].mem_bytes, 4_000_000_000);
+        // Disk quota is untouched, like a request without --disk
+        assert_eq!(simulated[0].disk_bytes, 5_000_000_000);
+        // The real allocations are unchanged
+        assert_eq!(allocations[0].cpu_quota, 400.0);
     }
 
     #[test]
-    fn test_check_request_insufficient_cpu() {
+    fn test_simulate_allocations_over_capacity_reports_zero() {
         let totals = SystemTotals {
             total_mem_gb: 16.0,
             total_cpu: 8,
             total_disk_gb: 100.0,
         };
-        let allocations = vec![UserAlloc {
-            uid: "1000".to_string(),
-            cpu_quota: 600.0,         // 6 CPUs
-            mem_bytes: 4_000_000_000, // 4 GB
-            disk_bytes: 0,
-        }];
+        let simulated = simulate_allocations(&[], &[(1001, 8, 16), (1002, 16, 32)]);
+
+        let (cpu, mem, _) =
+            calculate_available_resources(&totals, &simulated, None, &AllocationLimits::default());
+        assert_eq!((cpu, mem), (0, 0));
+    }
+
+    #[test]
+    fn test_parse_batch_file_valid() {
+        let contents = "[users]\nbob = { cpu = 2, mem = 4 }\nalice = { cpu = 8, mem = 16 }\n\n[users.1005]\ncpu = 1\nmem = 2\n";
+        assert_eq!(
+            parse_batch_file(contents).unwrap(),
+            vec![
+                ("1005".to_string(), 1, 2),
+                ("alice".to_string(), 8, 16),
+                ("bob".to_string(), 2, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_batch_file_invalid() {
+        // Out of range values and unknown keys are rejected before anything is applied
+        assert!(parse_batch_file("[users]\nalice = { cpu = 0, mem = 4 }\n").is_err());
+        assert!(parse_batch_file("[users]\nalice = { cpu = 1, mem = 10001 }\n").is_err());
+        assert!(parse_batch_file("[users]\nalice = { cpu = 1, mem = 4, disk = 8 }\n").is_err());
+        assert!(parse_batch_file("[users]\nalice = { cpu = 1 }\n").is_err());
+    }
+
+    #[test]
+    fn test_resolve_batch_users_reports_unknown_users() {
+        let entries = vec![
+            ("alice".to_string(), 8, 16),
+            ("ghost".to_string(), 1, 2),
+            ("nobody2".to_string(), 1, 2),
+        ];
+        let resolve = |user: &str| match user {
+            "alice" => Ok(1000),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "no such user")),
+        };
 
-        // Request 4 CPUs when only 2 are available - should fail
-        assert!(!check_request(&totals, &allocations, 4, "4", 0, None));
+        let err = resolve_batch_users(&entries, resolve).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("ghost, nobody2"));
+
+        assert_eq!(
+            resolve_batch_users(&entries[..1], resolve).unwrap(),
+            vec![(1000, 8, 16)]
+        );
     }
 
     #[test]
-    fn test_check_request_insufficient_memory() {
+    fn test_validate_batch_all_or_nothing() {
         let totals = SystemTotals {
-            total_mem_gb: 16.0,
-            total_cpu: 8,
+            total_mem_gb: 32.0,
+            total_cpu: 16,
             total_disk_gb: 100.0,
         };
-        let allocations = vec![UserAlloc {
-            uid: "1000".to_string(),
-            cpu_quota: 200.0,          // 2 CPUs
-            mem_bytes: 12_000_000_000, // 12 GB
-            disk_bytes: 0,
-        }];
+        let allocations = vec![alloc("1000", 400.0, 8, 0)];
+        let limits = AllocationLimits {
+            reserves: (2, 4, 0),
+            ..AllocationLimits::default()
+        };
 
-        // Request 8 GB when only 4 GB available - should fail
-        assert!(!check_request(&totals, &allocations, 2, "8", 0, None));
+        // 1000 is resized 4 -> 6 CPUs, so 6 + 8 = 14 of the 14 unreserved CPUs
+        assert!(validate_batch(
+            &totals,
+            &allocations,
+            &[(1000, 6, 8), (1001, 8, 16)],
+            &limits
+        )
+        .is_ok());
+
+        // Each entry fits next to 1000's 4 CPUs alone, but 4 + 6 + 6 is 2 CPUs too many
+        let rejections = validate_batch(
+            &totals,
+            &allocations,
+            &[(1001, 6, 8), (1002, 6, 8)],
+            &limits,
+        )
+        .unwrap_err();
+        assert_eq!(rejections.len(), 1);
+        assert_eq!((rejections[0].index, rejections[0].uid), (1, 1002));
+        assert!(
+            rejections[0].reason.contains("2.00 CPUs"),
+            "{:?}",
+            rejections
+        );
     }
 
     #[test]
-    fn test_check_request_multiple_users() {
+    fn test_validate_batch_names_only_the_entries_past_capacity() {
         let totals = SystemTotals {
             total_mem_gb: 32.0,
             total_cpu: 16,
             total_disk_gb: 100.0,
         };
-        let allocations = vec![
-            UserAlloc {
-                uid: "1000".to_string(),
-                cpu_quota: 400.0,         // 4 CPUs
-                mem_bytes: 8_000_000_000, // 8 GB
-                disk_bytes: 0,
-            },
-            UserAlloc {
-                uid: "1001".to_string(),
-                cpu_quota: 200.0,         // 2 CPUs
-                mem_bytes: 4_000_000_000, // 4 GB
-                disk_bytes: 0,
-            },
-        ];
+        let limits = AllocationLimits::default();
+
+        // 4 + 8 fit; the third entry goes 2 CPUs over and the fourth 4 more
+        let rejections = validate_batch(
+            &totals,
+            &[],
+            &[(1001, 4, 4), (1002, 8, 4), (1003, 6, 4), (1004, 4, 4)],
+            &limits,
+        )
+        .unwrap_err();
+        let rejected: Vec<(usize, u32)> = rejections.iter().map(|r| (r.index, r.uid)).collect();
+        assert_eq!(rejected, vec![(2, 1003), (3, 1004)]);
+        assert!(
+            rejections[0].reason.contains("2.00 CPUs"),
+            "{:?}",
+            rejections
+        );
+        assert!(
+            rejections[1].reason.contains("6.00 CPUs"),
+            "{:?}",
+            rejections
+        );
+
+        // Shrinking a large holder later in the batch makes room for the rest
+        let allocations = vec![alloc("1000", 1200.0, 8, 0)];
+        assert!(validate_batch(
+            &totals,
+            &allocations,
+            &[(1001, 8, 4), (1000, 2, 4)],
+            &limits
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_validate_batch_applies_request_policy_checks() {
+        let totals = SystemTotals {
+            total_mem_gb: 64.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let limits = AllocationLimits {
+            max_mem_per_cpu_gb: Some(4),
+            fair_share_users: Some(2),
+            ..AllocationLimits::default()
+        };
+
+        // Plenty of room overall, but 1001 breaks the ratio and 1002 is over its
+        // 8 CPU fair share
+        let rejections = validate_batch(
+            &totals,
+            &[],
+            &[(1001, 2, 16), (1002, 10, 8), (1003, 4, 8)],
+            &limits,
+        )
+        .unwrap_err();
+        let rejected: Vec<(usize, u32)> = rejections.iter().map(|r| (r.index, r.uid)).collect();
+        assert_eq!(rejected, vec![(0, 1001), (1, 1002)]);
+        assert!(
+            rejections[0].reason.contains("4G-per-CPU"),
+            "{:?}",
+            rejections
+        );
+        assert!(
+            rejections[1].reason.contains("2 active user(s)"),
+            "{:?}",
+            rejections
+        );
+
+        assert!(validate_batch(&totals, &[], &[(1001, 4, 16), (1002, 8, 8)], &limits).is_ok());
+    }
 
-        // 6 CPUs used, 12 GB used
-        // Request 5 CPUs and 10 GB - should be allowed (10 available, 20 available)
-        assert!(check_request(&totals, &allocations, 5, "10", 0, None));
+    #[test]
+    fn test_parse_batch_json() {
+        let entries = parse_batch_json(
+            r#"[{"uid": 1002, "cpu": 4, "mem": 8}, {"uid": 1001, "cpu": 1, "mem": 2}]"#,
+        )
+        .unwrap();
+        // Array order is kept, and entries look like parse_batch_file's
+        assert_eq!(
+            entries,
+            vec![("1002".to_string(), 4, 8), ("1001".to_string(), 1, 2)]
+        );
 
-        // Request 12 CPUs - should fail (only 10 available)
-        assert!(!check_request(&totals, &allocations, 12, "8", 0, None));
+        assert!(parse_batch_json(r#"[{"uid": 1001, "cpu": 4}]"#).is_err());
+        assert!(parse_batch_json(r#"[{"uid": 1001, "cpu": 4, "mem": 8, "disk": 1}]"#).is_err());
+        assert!(parse_batch_json(r#"{"uid": 1001, "cpu": 4, "mem": 8}"#).is_err());
+        // Same limits as the TOML file
+        let err = parse_batch_json(r#"[{"uid": 1001, "cpu": 0, "mem": 8}]"#).unwrap_err();
+        assert!(err.to_string().contains("1001: cpu 0"), "{}", err);
     }
 
     #[test]
-    fn test_check_request_exact_available() {
-        let cpu_reserve = get_system_cpu_reserve();
-        let mem_reserve = get_system_mem_reserve();
-        let disk_reserve = get_system_disk_reserve();
+    fn test_resolve_batch_users_rejects_repeated_users() {
+        let entries = vec![
+            ("alice".to_string(), 8, 16),
+            ("1000".to_string(), 1, 2),
+            ("bob".to_string(), 1, 2),
+        ];
+        let resolve = |user: &str| match user {
+            "alice" | "1000" => Ok(1000),
+            _ => Ok(1001),
+        };
+
+        let err = resolve_batch_users(&entries, resolve).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("more than once: 1000"), "{}", err);
+    }
 
+    #[test]
+    fn test_check_request_uses_reserves_from_config_path() {
         let totals = SystemTotals {
             total_mem_gb: 16.0,
             total_cpu: 8,
             total_disk_gb: 100.0,
         };
-        let allocations = vec![UserAlloc {
-            uid: "1000".to_string(),
-            cpu_quota: 400.0,         // 4 CPUs
-            mem_bytes: 8_000_000_000, // 8 GB
-            disk_bytes: 0,
-        }];
-
-        // Calculate actual available resources considering reserves
-        // Available = Total - Used - Reserve
-        // Available CPU = 8 - 4 - cpu_reserve
-        // Available MEM = 16 - 8 - mem_reserve
-        let available_cpu = (8u32 - 4u32).saturating_sub(cpu_reserve);
-        let available_mem = (16u32 - 8u32).saturating_sub(mem_reserve);
-        let available_disk = 100.0 - (disk_reserve as f64);
-
-        // Request exactly what's available (should succeed)
-        assert!(check_request(
-            &totals,
-            &allocations,
-            available_cpu,
-            &available_mem.to_string(),
-            available_disk as u32,
-            None
-        ));
-
-        // Request more than available (should fail)
-        assert!(!check_request(
-            &totals,
-            &allocations,
-            available_cpu + 1,
-            &available_mem.to_string(),
-            available_disk as u32,
-            None
-        ));
+        let dir =
+            std::env::temp_dir().join(format!("fairshare-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("policy.toml");
+        // Zero defaults so login users on the test machine don't count against the total
+        fs::write(
+            &path,
+            "[defaults]\ncpu = 0\nmem = 0\ndisk = 0\ncpu_reserve = 6\nmem_reserve = 12\n",
+        )
+        .unwrap();
+
+        let policy = crate::policy::load_policy_from(&path).ok();
+        let limits = AllocationLimits::new(policy.as_ref(), vec![], || 1);
+        let reserves = limits.reserves;
+        // 8 - 6 reserved = 2 CPUs, 16 - 12 reserved = 4 GB
+        let fits_within = check_request(&totals, &[], 2, "4", 0, None, &limits).is_ok();
+        let fits_over = check_request(&totals, &[], 3, "4", 0, None, &limits).is_ok();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!((reserves.0, reserves.1), (6, 12));
+        assert!(fits_within);
+        assert!(!fits_over);
     }
 
     #[test]
-    fn test_check_request_user_modifying_own_allocation() {
-        // Get the actual system reserves to ensure test accounts for them
-        let cpu_reserve = get_system_cpu_reserve();
-        let mem_reserve = get_system_mem_reserve();
+    fn test_overview_available_matches_check_request() {
+        let totals = SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        };
+        let allocations = vec![alloc("1000", 400.0, 8, 0)];
+        let limits = AllocationLimits {
+            reserves: (2, 4, 0),
+            holds: (1, 2),
+            min_free_pct: (25, 0),
+            ..AllocationLimits::default()
+        };
 
-        // Use larger system to accommodate reserves and test scenarios
+        let figures = overview_figures(&totals, &allocations, &limits);
+        // 1000's live slice only; no other login users
+        assert_eq!(figures.allocated, (4.0, 8.0, 0.0));
+        // The 25% floor (4 CPUs) beats the 2 CPU reserve; the hold counts as reserved
+        assert_eq!(figures.reserved, (5.0, 6.0, 0.0));
+        // 16 - 5 - 4 = 7 CPUs, 32 - 6 - 8 = 18 GB
+        assert_eq!(figures.available, (7.0, 18.0, 100.0));
+        assert_eq!(
+            check_request(&totals, &allocations, 7, "18", 0, None, &limits),
+            Ok(())
+        );
+        // Plain lack of capacity comes with no policy explanation
+        assert_eq!(
+            check_request(&totals, &allocations, 8, "18", 0, None, &limits),
+            Err(None)
+        );
+    }
+
+    #[test]
+    fn test_overview_counts_login_users_without_slices_at_default() {
         let totals = SystemTotals {
             total_mem_gb: 32.0,
             total_cpu: 16,
             total_disk_gb: 100.0,
         };
-        let allocations = vec![
-            UserAlloc {
-                uid: "1000".to_string(),
-                cpu_quota: 400.0,          // 4 CPUs
-                mem_bytes: 10_000_000_000, // 10 GB
-                disk_bytes: 0,
-            },
-            UserAlloc {
-                uid: "1001".to_string(),
-                cpu_quota: 200.0,         // 2 CPUs
-                mem_bytes: 5_000_000_000, // 5 GB
-                disk_bytes: 0,
-            },
-        ];
+        let allocations = vec![alloc("1000", 400.0, 8, 0)];
+        let limits = AllocationLimits {
+            defaults: (2, 4, 0),
+            login_uids: vec![1000, 1001],
+            ..AllocationLimits::default()
+        };
 
-        // Total used: 6 CPUs, 15 GB
-        // User 1000 requests 5 CPUs and 11 GB (increase of 1 CPU and 1 GB)
-        // Delta: adjusted_used = (6-4, 15-10) = (2 CPUs, 5 GB)
-        // Available = (16 - 2 - cpu_reserve, 32 - 5 - mem_reserve)
-        // With reserves (2, 4): Available = (12, 23)
-        // Request: 5 CPUs, 11 GB - should succeed since 5 <= 12 and 11 <= 23
-        assert!(check_request(
-            &totals,
-            &allocations,
-            5,
-            "11",
-            0,
-            Some("1000")
-        ));
+        // 1001 has never logged in but still holds the 2 CPU / 4 GB default
+        let figures = overview_figures(&totals, &allocations, &limits);
+        assert_eq!(figures.allocated, (6.0, 12.0, 0.0));
+        assert_eq!(figures.available, (10.0, 20.0, 100.0));
+
+        // Same figures once 1001's default slice is live
+        let mut with_slice = allocations.clone();
+        with_slice.push(alloc("1001", 200.0, 4, 0));
+        let live = overview_figures(&totals, &with_slice, &limits);
+        assert_eq!(live.allocated, figures.allocated);
+        assert_eq!(live.available, figures.available);
+    }
 
-        // User 1001 trying to request 1 CPU and 3 GB (decrease from 2 CPUs, 5 GB)
-        // Should definitely succeed as this is a decrease
-        assert!(check_request(
-            &totals,
-            &allocations,
-            1,
-            "3",
-            0,
-            Some("1001")
-        ));
+    #[test]
+    fn test_oversubscription_warning_reports_overage() {
+        let totals = SystemTotals {
+            total_mem_gb: 16.0,
+            total_cpu: 8,
+            total_disk_gb: 100.0,
+        };
 
-        // Calculate what's actually available for a new user
-        // Used: 6 CPUs, 15 GB
-        // Available = (16 - 6 - cpu_reserve, 32 - 15 - mem_reserve)
-        // With reserves (2, 4): Available = (8, 13)
-        let avail_cpu_for_new = (16u32 - 6u32).saturating_sub(cpu_reserve);
-        let avail_mem_for_new = (32u32 - 15u32).saturating_sub(mem_reserve);
+        // 7 CPUs allocated + 2 reserved on an 8 CPU box; RAM and disk fit
+        let warning = oversubscription_warning(&totals, (7.0, 10.0, 0.0), (2.0, 4.0, 0.0)).unwrap();
+        assert!(warning.contains("Oversubscribed"));
+        assert!(warning.contains("CPUs over by 1.00"));
+        assert!(!warning.contains("RAM"));
+        assert!(!warning.contains("Disk"));
 
-        // New user 1002 requesting within available (should succeed)
-        assert!(check_request(
-            &totals,
-            &allocations,
-            avail_cpu_for_new.min(1),
-            &avail_mem_for_new.min(1).to_string(),
-            0,
-            Some("1002")
-        ));
+        let warning = oversubscription_warning(&totals, (7.0, 14.0, 0.0), (2.0, 4.0, 0.0)).unwrap();
+        assert!(warning.contains("RAM over by 2.00G"));
+    }
 
-        // User 1000 requesting way too much even with delta (should fail)
-        // Current: 4 CPUs. Request: 20 CPUs. Net: +16 CPUs.
-        // Available with delta = (16 - 2 - cpu_reserve) = 12 or less
-        // 20 > 12, so should fail
-        assert!(!check_request(
-            &totals,
-            &allocations,
-            20,
-            "15",
-            0,
-            Some("1000")
-        ));
+    #[test]
+    fn test_oversubscription_warning_none_when_within_totals() {
+        let totals = SystemTotals {
+            total_mem_gb: 16.0,
+            total_cpu: 8,
+            total_disk_gb: 100.0,
+        };
+
+        // Exactly fully committed is not oversubscribed
+        assert_eq!(
+            oversubscription_warning(&totals, (6.0, 12.0, 50.0), (2.0, 4.0, 50.0)),
+            None
+        );
     }
 
     #[test]
     fn test_get_system_totals() {
-        let totals = get_system_totals();
+        let totals = get_system_totals(None);
 
         // Basic sanity checks
         assert!(totals.total_mem_gb > 0.0, "Total memory should be positive");
@@ -850,6 +2753,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_uid_from_slice_rejects_malformed_names() {
+        assert_eq!(parse_uid_from_slice("user-abc.slice"), None);
+        assert_eq!(parse_uid_from_slice("user-1000-extra.slice"), None);
+        assert_eq!(parse_uid_from_slice("user-1000\\x2dextra.slice"), None);
+        assert_eq!(parse_uid_from_slice("user-.slice"), None);
+        assert_eq!(parse_uid_from_slice("user-1000"), None);
+        assert_eq!(parse_uid_from_slice("user-1000.slice.slice"), None);
+        assert_eq!(parse_uid_from_slice("system-user-1000.slice"), None);
+        // Larger than u32
+        assert_eq!(parse_uid_from_slice("user-99999999999.slice"), None);
+    }
+
     #[test]
     fn test_system_slices_excluded_from_allocations() {
         // Regression test: ensure that user-0.slice (root/system) is not
@@ -869,28 +2785,6 @@ mod tests {
         // which skips any entry with UID "0"
     }
 
-    #[test]
-    fn test_check_request_insufficient_disk() {
-        let totals = SystemTotals {
-            total_mem_gb: 32.0,
-            total_cpu: 16,
-            total_disk_gb: 100.0,
-        };
-        let allocations = vec![UserAlloc {
-            uid: "1000".to_string(),
-            cpu_quota: 200.0,           // 2 CPUs
-            mem_bytes: 4_000_000_000,   // 4 GB
-            disk_bytes: 50_000_000_000, // 50 GB
-        }];
-
-        // Request 60 GB disk (Total 100 - Used 50 = 50 Available)
-        // 60 > 50 -> Should fail
-        assert!(!check_request(&totals, &allocations, 2, "4", 60, None));
-
-        // Request 40 GB disk -> Should succeed
-        assert!(check_request(&totals, &allocations, 2, "4", 40, None));
-    }
-
     #[test]
     fn test_get_uid_from_user_string_with_valid_uid() {
         // Test with current user's UID (should exist on the system)
@@ -950,6 +2844,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_login_users_uid_threshold() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\n\
+                      daemon:x:1:1:daemon:/usr/sbin:/bin/sh\n\
+                      sysacct:x:999:999::/home/sysacct:/bin/bash\n\
+                      alice:x:1000:1000:Alice:/home/alice:/bin/bash\n\
+                      bob:x:1001:1001::/home/bob:/bin/zsh\n\
+                      nobody:x:65534:65534:nobody:/nonexistent:/bin/sh\n";
+
+        let users = parse_login_users(passwd);
+        assert_eq!(
+            users,
+            vec![("alice".to_string(), 1000), ("bob".to_string(), 1001)]
+        );
+    }
+
+    #[test]
+    fn test_parse_login_users_excludes_nologin_shells() {
+        let passwd = "alice:x:1000:1000::/home/alice:/bin/bash\n\
+                      svc1:x:1002:1002::/var/lib/svc1:/usr/sbin/nologin\n\
+                      svc2:x:1003:1003::/var/lib/svc2:/sbin/nologin\n\
+                      svc3:x:1004:1004::/var/lib/svc3:/bin/false\n";
+
+        let users = parse_login_users(passwd);
+        assert_eq!(users, vec![("alice".to_string(), 1000)]);
+    }
+
+    #[test]
+    fn test_parse_login_users_skips_malformed_lines() {
+        let passwd = "# comment\n\
+                      \n\
+                      broken:x:1000\n\
+                      baduid:x:abc:1000::/home/baduid:/bin/bash\n\
+                      carol:x:1005:1005::/home/carol:/bin/bash\n";
+
+        let users = parse_login_users(passwd);
+        assert_eq!(users, vec![("carol".to_string(), 1005)]);
+    }
+
     #[test]
     fn test_get_uid_from_user_string_empty_string() {
         // Test with empty string
@@ -0,0 +1,465 @@
+//! `fairshare top`: a live, read-only view of user slices. The state and
+//! rendering are plain functions so they can be tested without a terminal;
+//! `run` only wires them to crossterm.
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, style::Print, terminal};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::cli::StatusSort;
+use crate::system::{
+    format_decimal, get_username_from_uid, parse_slices_show, run_systemctl_with_timeout,
+    SystemTotals,
+};
+use crate::systemd::parse_memory_current;
+
+/// Limits and usage counters of one user slice at one point in time
+#[derive(Clone, Debug, PartialEq)]
+pub struct SliceSample {
+    pub uid: String,
+    pub cpu_quota: f64,
+    pub mem_max: u64,
+    pub mem_current: u64,
+    pub cpu_usage_nsec: u64,
+}
+
+/// One line of the user table
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopRow {
+    pub uid: String,
+    pub username: String,
+    /// CPU limit in cores (0 when unlimited)
+    pub cpu_limit: f64,
+    /// CPU cores used on average since the previous sample
+    pub cpu_used: f64,
+    pub mem_limit_bytes: u64,
+    pub mem_used_bytes: u64,
+}
+
+pub struct TopState {
+    pub sort: StatusSort,
+    pub rows: Vec<TopRow>,
+    pub quit: bool,
+    /// CPUUsageNSec of each UID at the previous sample
+    previous_cpu_nsec: HashMap<String, u64>,
+}
+
+impl TopState {
+    pub fn new(sort: StatusSort) -> Self {
+        TopState {
+            sort,
+            rows: vec![],
+            quit: false,
+            previous_cpu_nsec: HashMap::new(),
+        }
+    }
+
+    /// Replace the rows with `samples`, taken `elapsed` after the previous ones
+    pub fn update<F>(&mut self, samples: Vec<SliceSample>, elapsed: Duration, username: F)
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let elapsed_nsec = elapsed.as_nanos() as f64;
+
+        self.rows = samples
+            .iter()
+            .map(|s| {
+                let cpu_used = match self.previous_cpu_nsec.get(&s.uid) {
+                    Some(&prev) if elapsed_nsec > 0.0 => {
+                        s.cpu_usage_nsec.saturating_sub(prev) as f64 / elapsed_nsec
+                    }
+                    _ => 0.0,
+                };
+                TopRow {
+                    uid: s.uid.clone(),
+                    username: username(&s.uid).unwrap_or_else(|| format!("({})", s.uid)),
+                    cpu_limit: s.cpu_quota / 100.0,
+                    cpu_used,
+                    mem_limit_bytes: s.mem_max,
+                    mem_used_bytes: s.mem_current,
+                }
+            })
+            .collect();
+
+        self.previous_cpu_nsec = samples
+            .into_iter()
+            .map(|s| (s.uid, s.cpu_usage_nsec))
+            .collect();
+        self.sort_rows();
+    }
+
+    /// q/Esc/Ctrl-C quit, s cycles the sort key, u and m sort by CPU and memory use
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('s') => self.sort = next_sort(self.sort),
+            KeyCode::Char('u') => self.sort = StatusSort::Cpu,
+            KeyCode::Char('m') => self.sort = StatusSort::Mem,
+            _ => return,
+        }
+        self.sort_rows();
+    }
+
+    /// cpu/mem: most used first; uid/user: ascending
+    fn sort_rows(&mut self) {
+        match self.sort {
+            StatusSort::Cpu => self.rows.sort_by(|a, b| b.cpu_used.total_cmp(&a.cpu_used)),
+            StatusSort::Mem => self
+                .rows
+                .sort_by_key(|r| std::cmp::Reverse(r.mem_used_bytes)),
+            StatusSort::Uid => self
+                .rows
+                .sort_by_key(|r| r.uid.parse::<u32>().unwrap_or(u32::MAX)),
+            StatusSort::User => self.rows.sort_by(|a, b| a.username.cmp(&b.username)),
+        }
+    }
+}
+
+fn next_sort(sort: StatusSort) -> StatusSort {
+    match sort {
+        StatusSort::Cpu => StatusSort::Mem,
+        StatusSort::Mem => StatusSort::Uid,
+        StatusSort::Uid => StatusSort::User,
+        StatusSort::User => StatusSort::Cpu,
+    }
+}
+
+fn sort_name(sort: StatusSort) -> &'static str {
+    match sort {
+        StatusSort::Cpu => "cpu",
+        StatusSort::Mem => "mem",
+        StatusSort::Uid => "uid",
+        StatusSort::User => "user",
+    }
+}
+
+/// `[#####-----]` filled to `used / total`
+fn usage_bar(used: f64, total: f64, width: usize) -> String {
+    let filled = if total > 0.0 {
+        ((used / total).clamp(0.0, 1.0) * width as f64).round() as usize
+    } else {
+        0
+    };
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+fn gb(bytes: u64) -> f64 {
+    bytes as f64 / 1_000_000_000.0
+}
+
+/// The screen as lines of text, each cut to `width` columns
+pub fn render(
+    state: &TopState,
+    totals: &SystemTotals,
+    interval: Duration,
+    width: usize,
+) -> Vec<String> {
+    let cpu_used: f64 = state.rows.iter().map(|r| r.cpu_used).sum();
+    let cpu_allocated: f64 = state.rows.iter().map(|r| r.cpu_limit).sum();
+    let mem_used: f64 = state.rows.iter().map(|r| gb(r.mem_used_bytes)).sum();
+    let mem_allocated: f64 = state.rows.iter().map(|r| gb(r.mem_limit_bytes)).sum();
+
+    let mut lines = vec![
+        format!(
+            "fairshare top - every {}s - sort: {}   (q quit, s cycle sort, u cpu, m mem)",
+            interval.as_secs(),
+            sort_name(state.sort)
+        ),
+        String::new(),
+        format!(
+            "CPU {} {:>8} used / {} total, {} allocated",
+            usage_bar(cpu_used, totals.total_cpu as f64, 30),
+            format_decimal(cpu_used),
+            totals.total_cpu,
+            format_decimal(cpu_allocated)
+        ),
+        format!(
+            "Mem {} {:>7}G used / {}G total, {}G allocated",
+            usage_bar(mem_used, totals.total_mem_gb, 30),
+            format_decimal(mem_used),
+            format_decimal(totals.total_mem_gb),
+            format_decimal(mem_allocated)
+        ),
+        String::new(),
+        format!(
+            "{:<16} {:>8} {:>9} {:>9} {:>10} {:>10}",
+            "USER", "UID", "CPU USED", "CPU LIMIT", "MEM USED", "MEM LIMIT"
+        ),
+    ];
+
+    for row in &state.rows {
+        let cpu_limit = if row.cpu_limit > 0.0 {
+            format_decimal(row.cpu_limit)
+        } else {
+            "-".to_string()
+        };
+        let mem_limit = if row.mem_limit_bytes > 0 {
+            format!("{}G", format_decimal(gb(row.mem_limit_bytes)))
+        } else {
+            "-".to_string()
+        };
+        lines.push(format!(
+            "{:<16} {:>8} {:>9} {:>9} {:>9}G {:>10}",
+            row.username,
+            row.uid,
+            format_decimal(row.cpu_used),
+            cpu_limit,
+            format_decimal(gb(row.mem_used_bytes)),
+            mem_limit
+        ));
+    }
+
+    lines
+        .into_iter()
+        .map(|line| line.chars().take(width).collect())
+        .collect()
+}
+
+/// Limits and usage of every user slice from one `systemctl show` call
+pub fn sample_user_slices() -> io::Result<Vec<SliceSample>> {
+    let output = run_systemctl_with_timeout(
+        &[
+            "show",
+            "user-*.slice",
+            "-p",
+            "Id",
+            "-p",
+            "MemoryMax",
+            "-p",
+            "CPUQuotaPerSecUSec",
+            "-p",
+            "MemoryCurrent",
+            "-p",
+            "CPUUsageNSec",
+        ],
+        Duration::from_secs(10),
+    )?;
+
+    Ok(parse_samples(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_samples(output: &str) -> Vec<SliceSample> {
+    output
+        .split("\n\n")
+        .filter_map(|block| {
+            let alloc = parse_slices_show(block).pop()?;
+            let cpu_usage_nsec = block
+                .lines()
+                .find_map(|l| l.strip_prefix("CPUUsageNSec="))
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(0);
+            Some(SliceSample {
+                uid: alloc.uid,
+                cpu_quota: alloc.cpu_quota,
+                mem_max: alloc.mem_bytes,
+                mem_current: parse_memory_current(block).unwrap_or(0),
+                cpu_usage_nsec,
+            })
+        })
+        .collect()
+}
+
+/// Full-screen view refreshed every `interval` until the user quits
+pub fn run(totals: &SystemTotals, interval: Duration) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let result = top_loop(&mut stdout, totals, interval);
+
+    // Restore the terminal even when sampling failed
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen).ok();
+    terminal::disable_raw_mode().ok();
+    result
+}
+
+fn top_loop(stdout: &mut io::Stdout, totals: &SystemTotals, interval: Duration) -> io::Result<()> {
+    let mut state = TopState::new(StatusSort::Cpu);
+
+    // CPU use is a rate, so take a short first sample to have something to show
+    let mut last_sample = Instant::now();
+    state.update(sample_user_slices()?, Duration::ZERO, get_username_from_uid);
+    std::thread::sleep(Duration::from_millis(250));
+
+    loop {
+        state.update(
+            sample_user_slices()?,
+            last_sample.elapsed(),
+            get_username_from_uid,
+        );
+        last_sample = Instant::now();
+        draw(stdout, &state, totals, interval)?;
+
+        let next = Instant::now() + interval;
+        while let Some(remaining) = next.checked_duration_since(Instant::now()) {
+            if !event::poll(remaining)? {
+                break;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                state.handle_key(key);
+                if state.quit {
+                    return Ok(());
+                }
+                draw(stdout, &state, totals, interval)?;
+            }
+        }
+    }
+}
+
+fn draw(
+    stdout: &mut io::Stdout,
+    state: &TopState,
+    totals: &SystemTotals,
+    interval: Duration,
+) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+    for (row, line) in render(state, totals, interval, width as usize)
+        .into_iter()
+        .take(height as usize)
+        .enumerate()
+    {
+        queue!(stdout, cursor::MoveTo(0, row as u16), Print(line))?;
+    }
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn sample(uid: &str, cpu_usage_nsec: u64, mem_current: u64) -> SliceSample {
+        SliceSample {
+            uid: uid.to_string(),
+            cpu_quota: 400.0,
+            mem_max: 8_000_000_000,
+            mem_current,
+            cpu_usage_nsec,
+        }
+    }
+
+    fn totals() -> SystemTotals {
+        SystemTotals {
+            total_mem_gb: 32.0,
+            total_cpu: 16,
+            total_disk_gb: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_handle_key_sort_transitions() {
+        let mut state = TopState::new(StatusSort::Cpu);
+
+        state.handle_key(key(KeyCode::Char('s')));
+        assert_eq!(state.sort, StatusSort::Mem);
+        state.handle_key(key(KeyCode::Char('s')));
+        assert_eq!(state.sort, StatusSort::Uid);
+        state.handle_key(key(KeyCode::Char('s')));
+        assert_eq!(state.sort, StatusSort::User);
+        state.handle_key(key(KeyCode::Char('s')));
+        assert_eq!(state.sort, StatusSort::Cpu);
+
+        state.handle_key(key(KeyCode::Char('m')));
+        assert_eq!(state.sort, StatusSort::Mem);
+        state.handle_key(key(KeyCode::Char('u')));
+        assert_eq!(state.sort, StatusSort::Cpu);
+
+        state.handle_key(key(KeyCode::Char('x')));
+        assert_eq!(state.sort, StatusSort::Cpu);
+        assert!(!state.quit);
+    }
+
+    #[test]
+    fn test_handle_key_quit() {
+        for quit_key in [
+            key(KeyCode::Char('q')),
+            key(KeyCode::Esc),
+            KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
+        ] {
+            let mut state = TopState::new(StatusSort::Cpu);
+            state.handle_key(quit_key);
+            assert!(state.quit);
+        }
+    }
+
+    #[test]
+    fn test_update_computes_cpu_rate_and_sorts() {
+        let mut state = TopState::new(StatusSort::Cpu);
+        let names = |uid: &str| Some(format!("user{}", uid));
+
+        state.update(
+            vec![sample("1000", 0, 1), sample("1001", 0, 2)],
+            Duration::ZERO,
+            names,
+        );
+        assert!(state.rows.iter().all(|r| r.cpu_used == 0.0));
+
+        // Over 2s, 1000 used 1s of CPU time (0.5 cores) and 1001 used 6s (3 cores)
+        state.update(
+            vec![
+                sample("1000", 1_000_000_000, 1),
+                sample("1001", 6_000_000_000, 2),
+            ],
+            Duration::from_secs(2),
+            names,
+        );
+        assert_eq!(state.rows[0].uid, "1001");
+        assert_eq!(state.rows[0].cpu_used, 3.0);
+        assert_eq!(state.rows[1].cpu_used, 0.5);
+        assert_eq!(state.rows[1].username, "user1000");
+
+        state.handle_key(key(KeyCode::Char('s'))); // mem
+        assert_eq!(state.rows[0].uid, "1001");
+        state.handle_key(key(KeyCode::Char('s'))); // uid
+        assert_eq!(state.rows[0].uid, "1000");
+    }
+
+    #[test]
+    fn test_render_overview_and_rows() {
+        let mut state = TopState::new(StatusSort::Uid);
+        state.update(
+            vec![sample("1000", 0, 2_000_000_000)],
+            Duration::ZERO,
+            |_| Some("alice".to_string()),
+        );
+
+        let lines = render(&state, &totals(), Duration::from_secs(2), 200);
+        assert!(lines[0].contains("sort: uid"));
+        assert!(lines[2].starts_with("CPU ["));
+        assert!(lines[3].contains("2.00G used / 32.00G total, 8.00G allocated"));
+        assert!(lines.last().unwrap().starts_with("alice"));
+        assert!(lines.last().unwrap().contains("4.00"));
+
+        let narrow = render(&state, &totals(), Duration::from_secs(2), 10);
+        assert!(narrow.iter().all(|l| l.chars().count() <= 10));
+    }
+
+    #[test]
+    fn test_parse_samples() {
+        let output = "Id=user-1000.slice\nMemoryMax=8000000000\nCPUQuotaPerSecUSec=4s\n\
+                      MemoryCurrent=1500000000\nCPUUsageNSec=42000\n\n\
+                      Id=user-0.slice\nMemoryMax=infinity\nCPUQuotaPerSecUSec=infinity\n\
+                      MemoryCurrent=[not set]\nCPUUsageNSec=[not set]\n";
+
+        assert_eq!(
+            parse_samples(output),
+            vec![SliceSample {
+                uid: "1000".to_string(),
+                cpu_quota: 400.0,
+                mem_max: 8_000_000_000,
+                mem_current: 1_500_000_000,
+                cpu_usage_nsec: 42_000,
+            }]
+        );
+    }
+}
@@ -36,6 +36,12 @@ fn test_status_command() {
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
 
+        // Containers and WSL1 without systemd get a dedicated error and exit code
+        if stderr.contains("requires systemd as PID 1") {
+            assert_eq!(output.status.code(), Some(3));
+            return;
+        }
+
         #[cfg(not(target_os = "linux"))]
         if stderr.contains("Failed to get user allocations")
             || stderr.contains("No such file or directory")
@@ -62,6 +68,29 @@ fn test_status_command() {
     );
 }
 
+#[test]
+fn test_status_totals_only() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "status", "--totals-only"])
+        .output()
+        .expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("requires systemd as PID 1") {
+        assert_eq!(output.status.code(), Some(3));
+        return;
+    }
+
+    assert!(
+        output.status.success(),
+        "status --totals-only failed: {}",
+        stderr
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("SYSTEM RESOURCE OVERVIEW"));
+    assert!(!stdout.contains("Per-User Allocations"));
+}
+
 #[test]
 fn test_info_command() {
     let output = Command::new("cargo")
@@ -79,10 +108,31 @@ fn test_info_command() {
             || stdout.contains("CPU Quota")
             || stdout.contains("Memory Max")
             || stderr.contains("Failed")
+            || stderr.contains("requires systemd as PID 1")
             || output.status.success()
     );
 }
 
+#[test]
+fn test_info_other_user_without_root_fails() {
+    // Without systemd every command exits 3 before getting this far
+    if !std::path::Path::new("/run/systemd/system").is_dir() {
+        return;
+    }
+
+    // Acting for UID 1000 through sudo, so --user is refused even when run as root
+    let output = Command::new("cargo")
+        .args(["run", "--", "info", "--user", "root"])
+        .env("SUDO_UID", "1000")
+        .env_remove("PKEXEC_UID")
+        .output()
+        .expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("--user requires root"), "{}", stderr);
+}
+
 #[test]
 fn test_request_without_args_fails() {
     let output = Command::new("cargo")
@@ -109,6 +159,74 @@ fn test_admin_help() {
     assert!(stdout.contains("setup"));
 }
 
+#[test]
+fn test_admin_prune_help() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "admin", "prune", "--help"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("users that no longer exist"));
+    assert!(stdout.contains("--dry-run"));
+}
+
+#[test]
+fn test_release_grace_help() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "release", "--help"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("--grace <DURATION>"));
+}
+
+#[test]
+fn test_release_rejects_bad_grace() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "release", "--grace", "2h"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown unit"));
+}
+
+#[test]
+fn test_request_fun_requires_all() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "request", "--fun"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--all"), "{}", stderr);
+}
+
+#[test]
+fn test_request_all_prints_no_art_when_piped() {
+    // stdout is captured, so it is not a TTY; without --fun there must be no art
+    // whether or not this machine lets the request through
+    let output = Command::new("cargo")
+        .args(["run", "--", "request", "--all", "--preview-command"])
+        .output()
+        .expect("Failed to execute command");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        !stdout
+            .chars()
+            .any(|c| ('\u{2800}'..='\u{28FF}').contains(&c)),
+        "unexpected ASCII art in: {}",
+        stdout
+    );
+}
+
 #[test]
 fn test_admin_uninstall_command_exists_in_help() {
     // Test that the uninstall command appears in the admin help output
@@ -360,6 +478,114 @@ fn test_request_cpu_above_maximum() {
     );
 }
 
+#[test]
+fn test_request_io_weight_out_of_range_rejected() {
+    for weight in ["0", "10001"] {
+        let output = Command::new("cargo")
+            .args([
+                "run",
+                "--",
+                "request",
+                "--cpu",
+                "1",
+                "--mem",
+                "2",
+                "--io-weight",
+                weight,
+            ])
+            .output()
+            .expect("Failed to execute command");
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("not in") || stderr.contains("invalid"),
+            "Expected validation error message about range"
+        );
+    }
+}
+
+#[test]
+fn test_request_cpu_pct_above_100_rejected() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "request", "--cpu-pct", "101", "--mem", "2"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("not in") || stderr.contains("invalid"),
+        "Expected validation error message about range"
+    );
+}
+
+#[test]
+fn test_request_cpu_pct_conflicts_with_cpu() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "request",
+            "--cpu",
+            "2",
+            "--cpu-pct",
+            "25",
+            "--mem",
+            "2",
+        ])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("cannot be used with"),
+        "Expected a conflict error, got: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_request_interactive_conflicts_with_cpu() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "request", "--interactive", "--cpu", "2"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("cannot be used with"),
+        "Expected a conflict error, got: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_request_cpu_guaranteed_requires_cpu_max() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "request",
+            "--cpu-guaranteed",
+            "2",
+            "--mem",
+            "4",
+        ])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--cpu-max"),
+        "Expected --cpu-max to be required, got: {}",
+        stderr
+    );
+}
+
 #[test]
 fn test_request_mem_below_minimum() {
     // Test that memory value below minimum (0) is rejected
@@ -457,7 +683,8 @@ fn test_request_minimum_valid_values() {
             || stderr.contains("resource")
             || stderr.contains("Interactive authentication required")
             || stderr.contains("Failed to set user limits")
-            || stderr.contains("Failed to get user allocations"),
+            || stderr.contains("Failed to get user allocations")
+            || stderr.contains("requires systemd as PID 1"),
         "Expected validation to pass for minimum valid values (1, 1), got: {}",
         stderr
     );
@@ -482,7 +709,8 @@ fn test_request_maximum_valid_values() {
             || stderr.contains("resource")
             || stderr.contains("Interactive authentication required")
             || stderr.contains("Failed to set user limits")
-            || stderr.contains("Failed to get user allocations"),
+            || stderr.contains("Failed to get user allocations")
+            || stderr.contains("requires systemd as PID 1"),
         "Expected validation to pass for maximum valid values (1000, 10000), got validation error: {}",
         stderr
     );
@@ -512,7 +740,8 @@ fn test_request_backwards_compatibility_without_disk() {
             || stderr.contains("resource")
             || stderr.contains("Interactive authentication required")
             || stderr.contains("Failed to set user limits")
-            || stderr.contains("Failed to get user allocations"),
+            || stderr.contains("Failed to get user allocations")
+            || stderr.contains("requires systemd as PID 1"),
         "Request without --disk should not require disk parameter, got: {}",
         stderr
     );
@@ -713,3 +942,261 @@ fn test_admin_setup_maximum_valid_values() {
         stderr
     );
 }
+
+#[test]
+fn test_quiet_flag_in_help() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "--help"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("--quiet"));
+}
+
+#[test]
+fn test_quiet_request_failure_has_no_stdout() {
+    // A request far beyond any real machine fails validation; with -q only the
+    // error on stderr should be produced
+    let output = Command::new("cargo")
+        .args([
+            "run", "--", "-q", "request", "--cpu", "1000", "--mem", "10000",
+        ])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.is_empty(), "Expected no stdout, got: {}", stdout);
+    assert!(
+        stderr.contains("✗"),
+        "Expected an error on stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_completions_bash() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "completions", "bash"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("request"));
+    assert!(stdout.contains("release"));
+    assert!(stdout.contains("admin"));
+    // Nested admin subcommands and their flags are included
+    assert!(stdout.contains("set-user"));
+    assert!(stdout.contains("--cpu-reserve"));
+}
+
+#[test]
+fn test_completions_rejects_unknown_shell() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "completions", "notashell"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_man_page_generation() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "man"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(".TH"));
+    assert!(stdout.contains("fairshare"));
+    // Nested admin subcommands get their own pages
+    assert!(
+        stdout.contains("fairshare\\-admin\\-setup") || stdout.contains("fairshare-admin-setup")
+    );
+}
+
+#[test]
+fn test_man_command_hidden_from_help() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "--help"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("man page"));
+}
+
+#[test]
+fn test_version_verbose() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "version", "--verbose"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(env!("CARGO_PKG_VERSION")));
+    assert!(stdout.lines().any(|l| l.starts_with("Target:")));
+    assert!(stdout.lines().any(|l| l.starts_with("Cgroups:")));
+}
+
+#[test]
+fn test_commands_report_missing_systemd() {
+    // Only meaningful where systemd isn't the init system (containers, WSL1)
+    if std::path::Path::new("/run/systemd/system").is_dir() {
+        return;
+    }
+
+    for args in [
+        vec!["status"],
+        vec!["info"],
+        vec!["allocations", "--json"],
+        vec!["release"],
+        vec!["request", "--cpu", "1", "--mem", "1"],
+    ] {
+        let output = Command::new("cargo")
+            .args(["run", "--"])
+            .args(&args)
+            .output()
+            .expect("Failed to execute command");
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(3), "{:?}: {}", args, stderr);
+        assert!(
+            stderr.contains("fairshare requires systemd as PID 1; this system appears to use"),
+            "{:?}: {}",
+            args,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn test_config_rejected_for_pkexec_callers() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "--config", "/tmp/policy.toml", "status"])
+        .env("PKEXEC_UID", "1000")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--config can only be used by root"));
+}
+
+#[test]
+fn test_admin_setup_print_writes_nothing() {
+    let dir = std::env::temp_dir().join(format!("fairshare-setup-print-{}", std::process::id()));
+    let policy = dir.join("policy.toml");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "--config"])
+        .arg(&policy)
+        .args(["admin", "setup", "--cpu", "2", "--mem", "4", "--print"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("# /etc/systemd/system/user-.slice.d/00-defaults.conf"));
+    assert!(stdout.contains("[Slice]\nCPUQuota=200%\nMemoryMax=4000000000\n"));
+    assert!(stdout.contains(&format!("# {}", policy.display())));
+    assert!(stdout.contains("[defaults]\ncpu = 2\nmem = 4\n"));
+    assert!(stdout.contains("50-fairshare.rules"));
+
+    // Dry run: the policy file and its directory must not be created
+    assert!(!dir.exists());
+}
+
+#[test]
+fn test_allocations_json_is_optional() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "allocations"])
+        .output()
+        .expect("Failed to execute command");
+
+    // No usage error without --json (it may still fail without systemd)
+    assert_ne!(output.status.code(), Some(2));
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "allocations", "--help"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("--json"));
+    assert!(stdout.contains("instead of a table"));
+}
+
+/// How assets/fairshare-wrapper.sh would run `fairshare <args>`: "direct" or "pkexec"
+fn wrapper_route(args: &[&str]) -> String {
+    let output = Command::new("bash")
+        .args([
+            "-c",
+            "source assets/fairshare-wrapper.sh && fairshare_route \"$@\"",
+            "fairshare-wrapper",
+        ])
+        .args(args)
+        .output()
+        .expect("Failed to run the wrapper");
+
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn test_wrapper_routes_after_quiet_flag() {
+    assert_eq!(wrapper_route(&["admin", "setup"]), "direct");
+    assert_eq!(wrapper_route(&["-q", "admin", "setup"]), "direct");
+    assert_eq!(wrapper_route(&["--quiet", "completions", "bash"]), "direct");
+    assert_eq!(wrapper_route(&["-q", "version"]), "direct");
+
+    assert_eq!(wrapper_route(&["request", "--cpu", "2"]), "pkexec");
+    assert_eq!(wrapper_route(&["-q", "release"]), "pkexec");
+    assert_eq!(wrapper_route(&[]), "pkexec");
+}
+
+#[test]
+fn test_wrapper_routes_after_config_option() {
+    // The path is --config's value, not the subcommand
+    assert_eq!(
+        wrapper_route(&["--config", "/srv/policy.toml", "admin", "setup"]),
+        "direct"
+    );
+    assert_eq!(
+        wrapper_route(&["--config=/srv/policy.toml", "admin", "setup"]),
+        "direct"
+    );
+    assert_eq!(
+        wrapper_route(&["-q", "--config", "admin", "status"]),
+        "pkexec"
+    );
+    assert_eq!(wrapper_route(&["--config"]), "pkexec");
+}
+
+#[test]
+fn test_wrapper_routes_after_debug_flag() {
+    assert_eq!(wrapper_route(&["--debug", "top"]), "direct");
+    assert_eq!(wrapper_route(&["--debug", "man"]), "direct");
+    assert_eq!(
+        wrapper_route(&[
+            "--debug",
+            "-q",
+            "--config",
+            "/srv/policy.toml",
+            "admin",
+            "setup"
+        ]),
+        "direct"
+    );
+    assert_eq!(
+        wrapper_route(&["--debug", "request", "--cpu", "2"]),
+        "pkexec"
+    );
+}
@@ -23,7 +23,10 @@ fn test_full_workflow_help_and_status() {
         let stderr = String::from_utf8_lossy(&status_output.stderr);
         // Allow failure on Linux if it's a known non-critical failure
         #[cfg(target_os = "linux")]
-        if stderr.contains("Failed to get user allocations") || stderr.contains("No such file") {
+        if stderr.contains("Failed to get user allocations")
+            || stderr.contains("No such file")
+            || stderr.contains("requires systemd as PID 1")
+        {
             return;
         }
         // On macOS/other platforms, status will fail as it requires systemd
@@ -54,6 +57,7 @@ fn test_request_validation() {
             || stderr.contains("Failed")
             || stderr.contains("exceeds")
             || stderr.contains("permission")
+            || stderr.contains("requires systemd as PID 1")
     );
 }
 
@@ -87,13 +91,14 @@ fn test_multiple_command_execution() {
         let output = Command::new("cargo")
             .args(["run", "--", cmd])
             .output()
-            .expect(&format!("Failed to run {}", cmd));
+            .unwrap_or_else(|_| panic!("Failed to run {}", cmd));
 
         // Commands should execute without panicking
         // They may fail due to permissions, but should not crash
         assert!(
             output.status.success()
                 || String::from_utf8_lossy(&output.stderr).contains("Failed")
+                || String::from_utf8_lossy(&output.stderr).contains("requires systemd as PID 1")
                 || String::from_utf8_lossy(&output.stdout).contains("MemoryMax")
         );
     }